@@ -0,0 +1,156 @@
+use std::borrow::Borrow;
+use std::cmp;
+use std::iter::FromIterator;
+use redblack::{ self, Comparator, Measure, Natural, OrdMap };
+
+// Sums the counts in a subtree, giving a bag's size in constant time.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Total;
+
+impl <T> Measure<T, usize> for Total {
+    type Summary = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn measure(_: &T, count: &usize) -> usize {
+        *count
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+/// A multiset, mapping each distinct element to the number of times it occurs.
+#[derive(Clone)]
+pub struct Bag<T, C = Natural> {
+    map: OrdMap<T, usize, C, Total>
+}
+
+impl <T> Bag<T> where T : Clone + Ord {
+    pub fn new() -> Bag<T> {
+        Bag::with_comparator(Natural)
+    }
+}
+
+impl <T,C> Bag<T,C> where T : Clone, C : Comparator<T> + Clone {
+    pub fn with_comparator(cmp: C) -> Bag<T,C> {
+        Bag { map: OrdMap::measured(cmp) }
+    }
+
+    pub fn insert(&self, value: T) -> Bag<T,C> {
+        self.insert_n(value, 1)
+    }
+
+    pub fn insert_n(&self, value: T, n: usize) -> Bag<T,C> {
+        if n == 0 {
+            return self.clone();
+        }
+        Bag { map: self.map.update_with(value, |count| Some(count.map_or(n, |c| c + n))) }
+    }
+
+    /// Removes one occurrence of `value`, if any.
+    pub fn remove<Q>(&self, value: &Q) -> Bag<T,C> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.remove_n(value, 1)
+    }
+
+    /// Removes up to `n` occurrences of `value`.
+    pub fn remove_n<Q>(&self, value: &Q, n: usize) -> Bag<T,C> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        match self.map.get_key_value(value) {
+            Some(_) if n == 0 => self.clone(),
+            Some((_, &count)) if count <= n => Bag { map: self.map.remove(value) },
+            Some((stored, &count)) => Bag { map: self.map.insert(stored.clone(), count - n) },
+            None => self.clone(),
+        }
+    }
+
+    /// The number of occurrences of `value`.
+    pub fn count<Q>(&self, value: &Q) -> usize where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.get(value).cloned().unwrap_or(0)
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.contains_key(value)
+    }
+
+    /// The number of elements in the bag, counting repetitions.
+    pub fn len(&self) -> usize {
+        self.map.summary()
+    }
+
+    /// The number of distinct elements in the bag.
+    pub fn distinct_len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterates over the elements in order, repeating each as often as it occurs.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { counts: self.map.iter(), current: None }
+    }
+}
+
+impl <T,C> Bag<T,C> where T : Clone, C : Comparator<T> + Clone + PartialEq {
+    /// Adds the counts of both bags.
+    pub fn sum(&self, other: &Bag<T,C>) -> Bag<T,C> {
+        Bag { map: self.map.union_with(&other.map, |_, a, b| a + b) }
+    }
+
+    /// Takes the greater count of each element.
+    pub fn union(&self, other: &Bag<T,C>) -> Bag<T,C> {
+        Bag { map: self.map.union_with(&other.map, |_, a, b| cmp::max(*a, *b)) }
+    }
+
+    /// Takes the lesser count of each element.
+    pub fn intersection(&self, other: &Bag<T,C>) -> Bag<T,C> {
+        Bag { map: self.map.intersection_with(&other.map, |_, a, b| cmp::min(*a, *b)) }
+    }
+}
+
+impl <T,C> Default for Bag<T,C> where T : Clone, C : Comparator<T> + Clone + Default {
+    fn default() -> Bag<T,C> {
+        Bag::with_comparator(C::default())
+    }
+}
+
+impl <T,C> FromIterator<T> for Bag<T,C> where T : Clone, C : Comparator<T> + Clone + Default {
+    fn from_iter<I>(iter: I) -> Self where I : IntoIterator<Item=T> {
+        iter.into_iter().fold(Bag::default(), |b, t| b.insert(t))
+    }
+}
+
+impl <'a, T, C> IntoIterator for &'a Bag<T,C> where T : Clone, C : Comparator<T> + Clone {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T>
+where T : 'a {
+    counts: redblack::Iter<'a, T, usize, Total>,
+    current: Option<(&'a T, usize)>
+}
+
+impl <'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        match self.current {
+            Some((value, ref mut left)) if *left > 0 => {
+                *left -= 1;
+                Some(value)
+            },
+            _ => {
+                let (value, &count) = self.counts.next()?;
+                self.current = Some((value, count - 1));
+                Some(value)
+            },
+        }
+    }
+}
@@ -6,6 +6,12 @@ pub struct List<T>(Option<Rc<Cons<T>>>);
 
 struct Cons <T> (T, List<T>);
 
+impl<T> List<T> {
+    pub fn iter(&self) -> ListIterator<T> {
+        ListIterator(self)
+    }
+}
+
 impl<T> List<T> where T : Clone {
     pub fn new() -> List<T> {
         List(None)
@@ -19,10 +25,6 @@ impl<T> List<T> where T : Clone {
           .fold(List(None), |l, e| l.cons(e))
     }
 
-    pub fn iter(&self) -> ListIterator<T> {
-        ListIterator(self)
-    }
-
     pub fn head_tail(&self) -> Option<(&T, List<T>)> {
         self.0.as_ref().map(|c| (&c.0, c.1.clone()))
     }
@@ -1,7 +1,9 @@
 #![crate_type = "lib"]
 
+pub mod bag;
 pub mod cons;
 mod hash;
+pub mod multimap;
 pub mod redblack;
 mod stream;
 pub mod vector;
@@ -0,0 +1,143 @@
+use std::borrow::Borrow;
+use std::iter::FromIterator;
+use cons::{ List, ListIterator };
+use redblack::{ self, Comparator, Natural, OrdMap };
+
+/// A map from each key to any number of values, held in a shared list per
+/// key. Values under a key are kept most recently inserted first.
+#[derive(Clone)]
+pub struct MultiMap<K, V, C = Natural> {
+    map: OrdMap<K, List<V>, C>,
+    len: usize
+}
+
+impl <K,V> MultiMap<K,V> where K : Clone + Ord, V : Clone {
+    pub fn new() -> MultiMap<K,V> {
+        MultiMap::with_comparator(Natural)
+    }
+}
+
+impl <K,V,C> MultiMap<K,V,C> where K : Clone, V : Clone, C : Comparator<K> + Clone {
+    pub fn with_comparator(cmp: C) -> MultiMap<K,V,C> {
+        MultiMap { map: OrdMap::with_comparator(cmp), len: 0 }
+    }
+
+    pub fn insert(&self, key: K, value: V) -> MultiMap<K,V,C> {
+        let map = self.map.update_with(key, |bucket| {
+            Some(bucket.map_or_else(List::new, List::clone).cons(value))
+        });
+        MultiMap { map, len: self.len + 1 }
+    }
+
+    /// Removes the most recently inserted occurrence of `value` under `key`.
+    pub fn remove_one<Q>(&self, key: &Q, value: &V) -> MultiMap<K,V,C>
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q>, V : PartialEq {
+        let (stored, bucket) = match self.map.get_key_value(key) {
+            Some((stored, bucket)) if bucket.iter().any(|v| v == value) => (stored, bucket),
+            _ => return self.clone(),
+        };
+        let mut before = Vec::new();
+        let mut rest = bucket.clone();
+        while let Some((head, tail)) = rest.clone().head_tail() {
+            rest = tail;
+            if head == value {
+                break;
+            }
+            before.push(head.clone());
+        }
+        let map = if before.is_empty() && rest.head().is_none() {
+            self.map.remove(key)
+        } else {
+            self.map.insert(stored.clone(), before.into_iter().rev().fold(rest, |l, v| l.cons(v)))
+        };
+        MultiMap { map, len: self.len - 1 }
+    }
+
+    pub fn remove_all<Q>(&self, key: &Q) -> MultiMap<K,V,C>
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        match self.map.get(key) {
+            Some(bucket) => MultiMap { map: self.map.remove(key), len: self.len - bucket.iter().count() },
+            None => self.clone(),
+        }
+    }
+
+    /// The values under `key`, most recently inserted first.
+    pub fn get_all<Q>(&self, key: &Q) -> GetAll<'_, V>
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        GetAll(self.map.get(key).map(List::iter))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.contains_key(key)
+    }
+
+    /// The number of values in the map, counting every value under each key.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn keys(&self) -> redblack::Keys<'_, K, List<V>> {
+        self.map.keys()
+    }
+
+    /// Iterates over every key and value, in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { entries: self.map.iter(), bucket: None }
+    }
+}
+
+impl <K,V,C> Default for MultiMap<K,V,C> where K : Clone, V : Clone, C : Comparator<K> + Clone + Default {
+    fn default() -> MultiMap<K,V,C> {
+        MultiMap::with_comparator(C::default())
+    }
+}
+
+impl <K,V,C> FromIterator<(K, V)> for MultiMap<K,V,C> where K : Clone, V : Clone, C : Comparator<K> + Clone + Default {
+    fn from_iter<I>(iter: I) -> Self where I : IntoIterator<Item=(K, V)> {
+        iter.into_iter().fold(MultiMap::default(), |m, (k, v)| m.insert(k, v))
+    }
+}
+
+impl <'a, K, V, C> IntoIterator for &'a MultiMap<K,V,C> where K : Clone, V : Clone, C : Comparator<K> + Clone {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+pub struct GetAll<'a, V>(Option<ListIterator<'a, V>>)
+where V : 'a;
+
+impl <'a, V> Iterator for GetAll<'a, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<&'a V> {
+        self.0.as_mut().and_then(Iterator::next)
+    }
+}
+
+pub struct Iter<'a, K, V>
+where K : 'a, V : 'a {
+    entries: redblack::Iter<'a, K, List<V>>,
+    bucket: Option<(&'a K, ListIterator<'a, V>)>
+}
+
+impl <'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, ref mut values)) = self.bucket {
+                if let Some(value) = values.next() {
+                    return Some((key, value));
+                }
+            }
+            let (key, bucket) = self.entries.next()?;
+            self.bucket = Some((key, bucket.iter()));
+        }
+    }
+}
@@ -1,37 +1,119 @@
 #![allow(dead_code)]
+use std::borrow::Borrow;
 use std::clone::Clone;
-use std::cmp::{ Ord, Ordering };
+use std::cmp::{ self, Ord, Ordering };
+use std::error::Error;
+use std::fmt::{ self, Debug, Display, Formatter };
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{ Bound, Range, RangeBounds };
+use std::ptr;
 use std::rc::Rc;
 
+/// A total order on keys of type `K`, used in place of `Ord` to arrange a
+/// tree. Maps and sets only combine with others holding an equal comparator.
+pub trait Comparator<K : ?Sized> {
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+}
+
+/// Orders keys by their `Ord` implementation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Natural;
+
+impl <K> Comparator<K> for Natural where K : Ord + ?Sized {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Orders keys by the reverse of their `Ord` implementation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Reverse;
+
+impl <K> Comparator<K> for Reverse where K : Ord + ?Sized {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+/// A monoid summarising entries. Every node caches the combined summary of
+/// its subtree, so aggregates over any key range cost O(log n).
+/// `combine` must be associative with `empty` as its identity.
+pub trait Measure<K, V> {
+    type Summary : Clone;
+    fn empty() -> Self::Summary;
+    fn measure(key: &K, value: &V) -> Self::Summary;
+    fn combine(left: &Self::Summary, right: &Self::Summary) -> Self::Summary;
+}
+
+/// The trivial measure, caching nothing.
+impl <K,V> Measure<K,V> for () {
+    type Summary = ();
+    fn empty() {}
+    fn measure(_: &K, _: &V) {}
+    fn combine(_: &(), _: &()) {}
+}
+
 #[derive(Clone, Eq, PartialEq)]
 enum Color {
     Red,
     Black
 }
 
-#[derive(Clone)]
-struct Node<K,V> {
+struct Node<K,V,M> where M : Measure<K,V> {
     color: Color,
     key: K,
     value: V,
-    left: Tree<K,V>,
-    right: Tree<K,V>
+    left: Tree<K,V,M>,
+    right: Tree<K,V,M>,
+    size: usize,
+    // The number of black nodes on the way down to a leaf, counting this one.
+    black_height: usize,
+    summary: M::Summary
 }
 
-#[derive(Clone)]
-struct Tree<K,V>(Option<Rc<Node<K,V>>>);
+impl <K,V,M> Clone for Node<K,V,M> where K : Clone, V : Clone, M : Measure<K,V> {
+    fn clone(&self) -> Self {
+        Node {
+            color: self.color.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+            left: self.left.clone(),
+            right: self.right.clone(),
+            size: self.size,
+            black_height: self.black_height,
+            summary: self.summary.clone()
+        }
+    }
+}
+
+struct Tree<K,V,M = ()>(Option<Rc<Node<K,V,M>>>) where M : Measure<K,V>;
 
-impl <K,V> Tree<K,V> where K : Clone + Ord, V : Clone {
-    fn wrap(node: Node<K,V>) -> Self {
+impl <K,V,M> Clone for Tree<K,V,M> where M : Measure<K,V> {
+    fn clone(&self) -> Self {
+        Tree(self.0.clone())
+    }
+}
+
+impl <K,V,M> Tree<K,V,M> where K : Clone, V : Clone, M : Measure<K,V> {
+    fn wrap(node: Node<K,V,M>) -> Self {
         Tree(Some(Rc::new(node)))
     }
 
+    fn node(color: Color, k: K, v: V, left: Self, right: Self) -> Self {
+        let size = 1 + left.len() + right.len();
+        let black_height = left.black_height() + if color == Color::Black { 1 } else { 0 };
+        let summary = M::combine(&M::combine(&left.summary(), &M::measure(&k, &v)),
+                                 &right.summary());
+        Tree::wrap(Node { color, key: k, value: v, left, right, size, black_height, summary })
+    }
+
     fn black(k: K, v: V, left: Self, right: Self) -> Self {
-        Tree::wrap(Node { color: Color::Black, key: k, value: v, left: left, right: right })
+        Tree::node(Color::Black, k, v, left, right)
     }
 
     fn red(k: K, v: V, left: Self, right: Self) -> Self {
-        Tree::wrap(Node { color: Color::Red, key: k, value: v, left: left, right: right })
+        Tree::node(Color::Red, k, v, left, right)
     }
 
     fn is_black(&self) -> bool {
@@ -41,6 +123,14 @@ impl <K,V> Tree<K,V> where K : Clone + Ord, V : Clone {
         }
     }
 
+    // Unlike `is_black`, false for the empty tree.
+    fn is_black_node(&self) -> bool {
+        match self.0 {
+            None => false,
+            Some(ref node) => node.color == Color::Black,
+        }
+    }
+
     fn is_red(&self) -> bool {
         match self.0 {
             None => false,
@@ -53,7 +143,7 @@ impl <K,V> Tree<K,V> where K : Clone + Ord, V : Clone {
             None => self.clone(),
             Some(ref node) => 
                 if node.color == Color::Red {
-                    Tree::wrap(Node { color: Color::Black, ..(**node).clone() })
+                    Tree::wrap(Node { color: Color::Black, black_height: node.black_height + 1, ..(**node).clone() })
                 } else {
                     self.clone()
                 }
@@ -65,7 +155,7 @@ impl <K,V> Tree<K,V> where K : Clone + Ord, V : Clone {
             None => self.clone(),
             Some(ref node) => 
                 if node.color == Color::Black {
-                    Tree::wrap(Node { color: Color::Red, ..(**node).clone() })
+                    Tree::wrap(Node { color: Color::Red, black_height: node.black_height - 1, ..(**node).clone() })
                 } else {
                     self.clone()
                 }
@@ -92,62 +182,39 @@ impl <K,V> Tree<K,V> where K : Clone + Ord, V : Clone {
         self.0.is_none()
     }
 
-    fn updated(&self, k: K, v: V, overwrite: bool) -> Self {
-        fn mk_tree<K : Clone + Ord, V : Clone> 
-            (is_black: bool, z: K, zv: V, l: Tree<K,V>, r: Tree<K,V>) -> Tree<K,V>
-            {
-                if is_black {
-                    Tree::black(z, zv, l, r)
-                } else {
-                    Tree::red(z, zv, l, r)
-                }
-            }
-        fn balance_left<K : Clone + Ord, V : Clone>
-            (is_black: bool, z: K, zv: V, l: Tree<K,V>, d: Tree<K,V>) -> Tree<K,V> 
-            {
-                if l.is_red() && l.left().is_red() {
-                    Tree::red(l.key(), l.value(), 
-                              Tree::black(l.left().key(), l.left().value(), l.left().left(), l.left().right()),
-                              Tree::black(z, zv, l.right(), d))
-                } else if l.is_red() && l.right().is_red() {
-                    Tree::red(l.right().key(), l.right().value(), 
-                              Tree::black(l.key(), l.value(), l.left(), l.right().left()),
-                              Tree::black(z, zv, l.right().right(), d))
-                } else {
-                    mk_tree(is_black, z, zv, l, d)
-                }
-            }
-        fn balance_right<K : Clone + Ord, V : Clone>
-            (is_black: bool, x: K, xv: V, a: Tree<K,V>, r: Tree<K,V>) -> Tree<K,V>
-            {
-                if r.is_red() && r.left().is_red() {
-                    Tree::red(r.left().key(), r.left().value(),
-                    Tree::black(x, xv, a, r.left().left()),
-                    Tree::black(r.key(), r.value(), r.left().right(), r.right()))
-                } else if r.is_red() && r.right().is_red() {
-                    Tree::red(r.key(), r.value(),
-                    Tree::black(x, xv, a, r.left()),
-                    Tree::black(r.right().key(), r.right().value(), r.right().left(), r.right().right()))
-                } else {
-                    mk_tree(is_black, x, xv, a, r)
-                }
-            }
+    fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |n| n.size)
+    }
+
+    fn black_height(&self) -> usize {
+        self.0.as_ref().map_or(0, |n| n.black_height)
+    }
+
+    // Whether `node` is the root of this tree.
+    fn is_node(&self, node: &Node<K,V,M>) -> bool {
+        self.0.as_ref().is_some_and(|n| ptr::eq(&**n, node))
+    }
+
+    fn summary(&self) -> M::Summary {
+        self.0.as_ref().map_or_else(M::empty, |n| n.summary.clone())
+    }
 
+    fn updated<C>(&self, k: K, v: V, overwrite: bool, cmp: &C) -> Self where C : Comparator<K> {
         if self.is_empty() {
             Tree::red(k, v, Tree(None), Tree(None))
         } else {
-            match k.cmp(&self.key()) {
+            match cmp.compare(&k, &self.key()) {
                 Ordering::Less =>
-                    balance_left(self.is_black(), self.key(), self.value(),
-                    self.left().updated(k, v, overwrite),
+                    insertion::balance_left(self.is_black(), self.key(), self.value(),
+                    self.left().updated(k, v, overwrite, cmp),
                     self.right()),
                     Ordering::Greater =>
-                        balance_right(self.is_black(), self.key(), self.value(),
+                        insertion::balance_right(self.is_black(), self.key(), self.value(),
                         self.left(),
-                        self.right().updated(k, v, overwrite)),
+                        self.right().updated(k, v, overwrite, cmp)),
                         Ordering::Equal =>
                             if overwrite {
-                                mk_tree(self.is_black(), k, v, self.left(), self.right())
+                                insertion::mk_tree(self.is_black(), k, v, self.left(), self.right())
                             } else {
                                 self.clone()
                             },
@@ -155,164 +222,1791 @@ impl <K,V> Tree<K,V> where K : Clone + Ord, V : Clone {
         }
     }
 
-    fn removed(&self, k: K) -> Self {
-        fn balance<K : Clone + Ord, V : Clone>(
-            x: K, xv: V, tl: Tree<K,V>, tr: Tree<K,V>) -> Tree<K,V> {
-            if tl.is_red() {
-                if tr.is_red() {
-                    Tree::red(x, xv, 
-                              tl.to_black(),
-                              tr.to_black())
-                } else if tl.left().is_red() {
-                    Tree::red(tl.key(), tl.value(), 
-                              tl.left().to_black(), 
-                              Tree::black(x, xv, tl.right(), tr))
-                } else if tl.right().is_red() {
-                    Tree::red(tl.right().key(), tl.right().value(), 
-                              Tree::black(tl.key(), tl.value(), tl.left(), tl.right().left()), 
-                              Tree::black(x, xv, tl.right().right(), tr))
+    // Removes `k` in a single descent, or returns `None` if it is absent.
+    fn removed<Q, C>(&self, k: &Q, cmp: &C) -> Option<Self> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let node = self.0.as_ref()?;
+        Some(match cmp.compare(k, node.key.borrow()) {
+            Ordering::Less => {
+                let l = node.left.removed(k, cmp)?;
+                if node.left.is_black_node() {
+                    deletion::balance_left(self.key(), self.value(), l, self.right())
                 } else {
-                    Tree::black(x, xv, tl, tr)
+                    Tree::red(self.key(), self.value(), l, self.right())
                 }
-            } else if tr.is_red() {
-                if tr.right().is_red() {
-                    Tree::red(tr.key(), tr.value(),
-                        Tree::black(x, xv, tl, tr.left()),
-                        tr.right().to_black())
-                } else if tr.left().is_red() {
-                    Tree::red(tr.left().key(), tr.left().value(),
-                        Tree::black(x, xv, tl, tr.left().left()),
-                        Tree::black(tr.key(), tr.value(), tr.left().right(), tr.right()))
+            },
+            Ordering::Greater => {
+                let r = node.right.removed(k, cmp)?;
+                if node.right.is_black_node() {
+                    deletion::balance_right(self.key(), self.value(), self.left(), r)
                 } else {
-                    Tree::black(x, xv, tl, tr)
+                    Tree::red(self.key(), self.value(), self.left(), r)
                 }
+            },
+            Ordering::Equal => deletion::append(self.left(), self.right())
+        })
+    }
+
+    // Applies `f` to the value at `k` in a single descent, inserting,
+    // replacing or removing the entry as `f` dictates. A replacement that
+    // `same` considers equal to the current value leaves the tree unchanged.
+    fn modified<F, S, C>(&self, k: K, f: F, same: S, cmp: &C) -> Edit<K,V,M>
+        where F : FnOnce(Option<&V>) -> Option<V>, S : FnOnce(&V, &V) -> bool, C : Comparator<K> {
+        match self.0 {
+            None => match f(None) {
+                Some(v) => Edit::Inserted(Tree::red(k, v, Tree(None), Tree(None))),
+                None => Edit::Unchanged,
+            },
+            Some(ref node) => {
+                let is_black = node.color == Color::Black;
+                match cmp.compare(&k, &node.key) {
+                    Ordering::Less => match node.left.modified(k, f, same, cmp) {
+                        Edit::Unchanged => Edit::Unchanged,
+                        Edit::Replaced(l) =>
+                            Edit::Replaced(insertion::mk_tree(is_black, self.key(), self.value(), l, self.right())),
+                        Edit::Inserted(l) =>
+                            Edit::Inserted(insertion::balance_left(is_black, self.key(), self.value(), l, self.right())),
+                        Edit::Removed(l) =>
+                            Edit::Removed(if node.left.is_black_node() {
+                                deletion::balance_left(self.key(), self.value(), l, self.right())
+                            } else {
+                                Tree::red(self.key(), self.value(), l, self.right())
+                            }),
+                    },
+                    Ordering::Greater => match node.right.modified(k, f, same, cmp) {
+                        Edit::Unchanged => Edit::Unchanged,
+                        Edit::Replaced(r) =>
+                            Edit::Replaced(insertion::mk_tree(is_black, self.key(), self.value(), self.left(), r)),
+                        Edit::Inserted(r) =>
+                            Edit::Inserted(insertion::balance_right(is_black, self.key(), self.value(), self.left(), r)),
+                        Edit::Removed(r) =>
+                            Edit::Removed(if node.right.is_black_node() {
+                                deletion::balance_right(self.key(), self.value(), self.left(), r)
+                            } else {
+                                Tree::red(self.key(), self.value(), self.left(), r)
+                            }),
+                    },
+                    Ordering::Equal => match f(Some(&node.value)) {
+                        Some(ref v) if same(&node.value, v) => Edit::Unchanged,
+                        Some(v) => Edit::Replaced(insertion::mk_tree(is_black, k, v, self.left(), self.right())),
+                        None => Edit::Removed(deletion::append(self.left(), self.right())),
+                    },
+                }
+            }
+        }
+    }
+
+    fn lookup<Q, C>(&self, k: &Q, cmp: &C) -> Option<&Rc<Node<K,V,M>>>
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let mut tree = self;
+        while let Some(ref node) = tree.0 {
+            match cmp.compare(k, node.key.borrow()) {
+                Ordering::Less => tree = &node.left,
+                Ordering::Greater => tree = &node.right,
+                Ordering::Equal => return Some(node),
+            }
+        }
+        None
+    }
+
+    pub fn contains<Q, C>(&self, k: &Q, cmp: &C) -> bool where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.lookup(k, cmp).is_some()
+    }
+
+    pub fn get<Q, C>(&self, k: &Q, cmp: &C) -> Option<&V> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.lookup(k, cmp).map(|n| &n.value)
+    }
+
+    fn rank<Q, C>(&self, k: &Q, cmp: &C) -> usize where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let mut tree = self;
+        let mut rank = 0;
+        while let Some(ref node) = tree.0 {
+            match cmp.compare(k, node.key.borrow()) {
+                Ordering::Less => tree = &node.left,
+                Ordering::Greater => {
+                    rank += node.left.len() + 1;
+                    tree = &node.right;
+                },
+                Ordering::Equal => return rank + node.left.len(),
+            }
+        }
+        rank
+    }
+
+    fn select(&self, mut index: usize) -> Option<(&K, &V)> {
+        let mut tree = self;
+        while let Some(ref node) = tree.0 {
+            let left = node.left.len();
+            match index.cmp(&left) {
+                Ordering::Less => tree = &node.left,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    tree = &node.right;
+                },
+                Ordering::Equal => return Some((&node.key, &node.value)),
+            }
+        }
+        None
+    }
+
+    fn first(&self) -> Option<(&K, &V)> {
+        let mut node = self.0.as_ref();
+        while let Some(n) = node {
+            match n.left.0 {
+                Some(ref l) => node = Some(l),
+                None => return Some((&n.key, &n.value)),
+            }
+        }
+        None
+    }
+
+    fn last(&self) -> Option<(&K, &V)> {
+        let mut node = self.0.as_ref();
+        while let Some(n) = node {
+            match n.right.0 {
+                Some(ref r) => node = Some(r),
+                None => return Some((&n.key, &n.value)),
+            }
+        }
+        None
+    }
+
+    // The entry nearest `k` on the `toward` side of it, or at `k` itself if
+    // `inclusive`, found in a single descent.
+    fn nearest<Q, C>(&self, k: &Q, toward: Ordering, inclusive: bool, cmp: &C) -> Option<(&K, &V)>
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let mut tree = self;
+        let mut best = None;
+        while let Some(ref node) = tree.0 {
+            let ord = cmp.compare(node.key.borrow(), k);
+            if ord == Ordering::Equal && inclusive {
+                return Some((&node.key, &node.value));
+            }
+            let (near, far) = if toward == Ordering::Greater {
+                (&node.left, &node.right)
             } else {
-                Tree::black(x, xv, tl, tr)
+                (&node.right, &node.left)
+            };
+            if ord == toward {
+                best = Some(node);
+                tree = near;
+            } else {
+                tree = far;
             }
         }
+        best.map(|n| (&n.key, &n.value))
+    }
+
+    fn floor<Q, C>(&self, k: &Q, cmp: &C) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.nearest(k, Ordering::Less, true, cmp)
+    }
+
+    fn ceiling<Q, C>(&self, k: &Q, cmp: &C) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.nearest(k, Ordering::Greater, true, cmp)
+    }
+
+    fn lower<Q, C>(&self, k: &Q, cmp: &C) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.nearest(k, Ordering::Less, false, cmp)
+    }
+
+    fn higher<Q, C>(&self, k: &Q, cmp: &C) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.nearest(k, Ordering::Greater, false, cmp)
+    }
+
+    fn pop_first<C>(&self, cmp: &C) -> Option<((K, V), Self)> where C : Comparator<K> {
+        let (k, v) = self.first().map(|(k, v)| (k.clone(), v.clone()))?;
+        let rest = self.removed(&k, cmp)?;
+        Some(((k, v), rest))
+    }
+
+    fn pop_last<C>(&self, cmp: &C) -> Option<((K, V), Self)> where C : Comparator<K> {
+        let (k, v) = self.last().map(|(k, v)| (k.clone(), v.clone()))?;
+        let rest = self.removed(&k, cmp)?;
+        Some(((k, v), rest))
+    }
+
+    fn iter(&self) -> Iter<'_, K, V, M> {
+        let mut iter = Iter::empty();
+        iter.push_left(self);
+        iter.push_right(self);
+        iter
+    }
+
+    fn range<Q, R, C>(&self, range: R, cmp: &C) -> Iter<'_, K, V, M>
+        where K : Borrow<Q>, Q : ?Sized, R : RangeBounds<Q>, C : Comparator<Q> {
+        let mut iter = Iter::empty();
+        iter.seek_front(self, range.start_bound(), cmp);
+        iter.seek_back(self, range.end_bound(), cmp);
+        match (iter.front.last(), iter.back.last()) {
+            (Some(first), Some(last))
+                if cmp.compare(first.key.borrow(), last.key.borrow()) != Ordering::Greater => iter,
+            _ => Iter::empty(),
+        }
+    }
+
+    // Combines the summaries of the entries within the bounds. Whole subtrees
+    // inside the bounds contribute their cached summary.
+    fn summarize<Q, C>(&self, lo: Bound<&Q>, hi: Bound<&Q>, cmp: &C) -> M::Summary
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let node = match self.0 {
+            None => return M::empty(),
+            Some(ref node) => node,
+        };
+        if let (Bound::Unbounded, Bound::Unbounded) = (lo, hi) {
+            return node.summary.clone();
+        }
+        let above_lo = match lo {
+            Bound::Included(k) => cmp.compare(node.key.borrow(), k) != Ordering::Less,
+            Bound::Excluded(k) => cmp.compare(node.key.borrow(), k) == Ordering::Greater,
+            Bound::Unbounded => true,
+        };
+        let below_hi = match hi {
+            Bound::Included(k) => cmp.compare(node.key.borrow(), k) != Ordering::Greater,
+            Bound::Excluded(k) => cmp.compare(node.key.borrow(), k) == Ordering::Less,
+            Bound::Unbounded => true,
+        };
+        if !above_lo {
+            node.right.summarize(lo, hi, cmp)
+        } else if !below_hi {
+            node.left.summarize(lo, hi, cmp)
+        } else {
+            let left = node.left.summarize(lo, Bound::Unbounded, cmp);
+            let right = node.right.summarize(Bound::Unbounded, hi, cmp);
+            M::combine(&M::combine(&left, &M::measure(&node.key, &node.value)), &right)
+        }
+    }
+
+    fn keys(&self) -> Keys<'_, K, V, M> {
+        Keys(self.iter())
+    }
+
+    fn values(&self) -> Values<'_, K, V, M> {
+        Values(self.iter())
+    }
 
-        fn subl<K : Clone + Ord, V : Clone>(t: Tree<K,V>)  -> Tree<K,V> {
-            assert!(t.is_black());
-            t.to_red()
+    // Checks the subtree against the red-black and search tree invariants,
+    // returning its black height. Keys must lie strictly between `lo` and `hi`.
+    fn check<C>(&self, lo: Option<&K>, hi: Option<&K>, cmp: &C) -> Result<usize, InvariantError<K>>
+        where C : Comparator<K> {
+        match self.0 {
+            None => Ok(0),
+            Some(ref node) => {
+                if lo.is_some_and(|lo| cmp.compare(&node.key, lo) != Ordering::Greater) ||
+                    hi.is_some_and(|hi| cmp.compare(&node.key, hi) != Ordering::Less) {
+                    return Err(InvariantError::KeyOrder(node.key.clone()));
+                }
+                if node.color == Color::Red && (node.left.is_red() || node.right.is_red()) {
+                    return Err(InvariantError::RedRed(node.key.clone()));
+                }
+                let left = node.left.check(lo, Some(&node.key), cmp)?;
+                let right = node.right.check(Some(&node.key), hi, cmp)?;
+                if left != right {
+                    return Err(InvariantError::BlackHeight(node.key.clone()));
+                }
+                if node.size != 1 + node.left.len() + node.right.len() {
+                    return Err(InvariantError::Size(node.key.clone()));
+                }
+                let height = if node.color == Color::Black { left + 1 } else { left };
+                if node.black_height != height {
+                    return Err(InvariantError::BlackHeight(node.key.clone()));
+                }
+                Ok(height)
+            }
         }
+    }
 
-        fn balance_left<K : Clone + Ord, V : Clone>
-            (x: K, xv: V, tl: Tree<K,V>, tr: Tree<K,V>) -> Tree<K,V> 
+    // Builds a tree from entries with strictly increasing keys in O(n). Every
+    // level is black except a partially filled bottom level, which is red.
+    fn from_sorted<C>(entries: Vec<(K, V)>, cmp: &C) -> Tree<K,V,M> where C : Comparator<K> {
+        fn build<K : Clone, V : Clone, M : Measure<K,V>, I : Iterator<Item=(K, V)>>
+            (entries: &mut I, n: usize, depth: usize, red_depth: usize) -> Tree<K,V,M>
         {
-                if tl.is_red() {
-                    Tree::red(x, xv, tl.to_black(), tr)
-                } else if tr.is_black() {
-                    balance(x, xv, tl, tr.to_red())
-                } else if tr.is_red() && tr.left().is_black() {
-                    Tree::red(tr.left().key(), tr.right().value(),
-                        Tree::black(x, xv, tl, tr.left().left()),
-                        balance(tr.key(), tr.value(), tr.left().right(), subl(tr.right())))
-                } else {
-                    unreachable!()
+            if n == 0 {
+                return Tree(None);
+            }
+            let left = build(entries, (n - 1) / 2, depth + 1, red_depth);
+            let (k, v) = entries.next().unwrap();
+            let right = build(entries, n / 2, depth + 1, red_depth);
+            if depth == red_depth {
+                Tree::red(k, v, left, right)
+            } else {
+                Tree::black(k, v, left, right)
+            }
+        }
+
+        assert!(entries.windows(2).all(|w| cmp.compare(&w[0].0, &w[1].0) == Ordering::Less),
+                "Tried to build a tree from keys that are not strictly increasing");
+        let n = entries.len();
+        let red_depth = (usize::BITS - (n + 1).leading_zeros() - 1) as usize;
+        build(&mut entries.into_iter(), n, 0, red_depth)
+    }
+
+    fn join(tl: Tree<K,V,M>, k: K, v: V, tr: Tree<K,V,M>) -> Tree<K,V,M> {
+        fn join_right<K : Clone, V : Clone, M : Measure<K,V>>
+            (tl: Tree<K,V,M>, hl: usize, k: K, v: V, tr: Tree<K,V,M>, hr: usize) -> Tree<K,V,M>
+        {
+            if tl.is_black() && hl == hr {
+                return Tree::red(k, v, tl, tr);
+            }
+            let hl = if tl.is_black() { hl - 1 } else { hl };
+            let r = join_right(tl.right(), hl, k, v, tr, hr);
+            if tl.is_black() && r.is_red() && r.right().is_red() {
+                Tree::red(r.key(), r.value(),
+                          Tree::black(tl.key(), tl.value(), tl.left(), r.left()),
+                          r.right().to_black())
+            } else if tl.is_black() {
+                Tree::black(tl.key(), tl.value(), tl.left(), r)
+            } else {
+                Tree::red(tl.key(), tl.value(), tl.left(), r)
+            }
+        }
+
+        fn join_left<K : Clone, V : Clone, M : Measure<K,V>>
+            (tl: Tree<K,V,M>, hl: usize, k: K, v: V, tr: Tree<K,V,M>, hr: usize) -> Tree<K,V,M>
+        {
+            if tr.is_black() && hl == hr {
+                return Tree::red(k, v, tl, tr);
+            }
+            let hr = if tr.is_black() { hr - 1 } else { hr };
+            let l = join_left(tl, hl, k, v, tr.left(), hr);
+            if tr.is_black() && l.is_red() && l.left().is_red() {
+                Tree::red(l.key(), l.value(),
+                          l.left().to_black(),
+                          Tree::black(tr.key(), tr.value(), l.right(), tr.right()))
+            } else if tr.is_black() {
+                Tree::black(tr.key(), tr.value(), l, tr.right())
+            } else {
+                Tree::red(tr.key(), tr.value(), l, tr.right())
+            }
+        }
+
+        // Only the shorter tree hangs below a new red node, so only its root
+        // needs to be black.
+        let hl = tl.black_height();
+        let hr = tr.black_height();
+        if hl > hr && tr.is_red() {
+            Tree::join(tl, k, v, tr.to_black())
+        } else if hr > hl && tl.is_red() {
+            Tree::join(tl.to_black(), k, v, tr)
+        } else if hl > hr {
+            let t = join_right(tl, hl, k, v, tr, hr);
+            if t.is_red() && t.right().is_red() { t.to_black() } else { t }
+        } else if hr > hl {
+            let t = join_left(tl, hl, k, v, tr, hr);
+            if t.is_red() && t.left().is_red() { t.to_black() } else { t }
+        } else if tl.is_black() && tr.is_black() {
+            Tree::red(k, v, tl, tr)
+        } else {
+            Tree::black(k, v, tl, tr)
+        }
+    }
+
+    fn split_last(&self) -> (Tree<K,V,M>, K, V) {
+        let node = self.0.as_ref().unwrap();
+        if node.right.is_empty() {
+            (node.left.clone(), node.key.clone(), node.value.clone())
+        } else {
+            let (rest, k, v) = node.right.split_last();
+            (Tree::join(node.left.clone(), node.key.clone(), node.value.clone(), rest), k, v)
+        }
+    }
+
+    // Joins two trees whose keys are already in order, without a separating entry.
+    fn concat(tl: Tree<K,V,M>, tr: Tree<K,V,M>) -> Tree<K,V,M> {
+        if tl.is_empty() {
+            tr
+        } else {
+            let (tl, k, v) = tl.split_last();
+            Tree::join(tl, k, v, tr)
+        }
+    }
+
+    fn split<Q, C>(&self, k: &Q, cmp: &C) -> (Self, Option<V>, Self)
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        match self.0 {
+            None => (Tree(None), None, Tree(None)),
+            Some(ref node) => match cmp.compare(k, node.key.borrow()) {
+                Ordering::Less => {
+                    let (l, v, r) = node.left.split(k, cmp);
+                    (l, v, Tree::join(r, node.key.clone(), node.value.clone(), node.right.clone()))
+                },
+                Ordering::Greater => {
+                    let (l, v, r) = node.right.split(k, cmp);
+                    (Tree::join(node.left.clone(), node.key.clone(), node.value.clone(), l), v, r)
+                },
+                Ordering::Equal => (node.left.clone(), Some(node.value.clone()), node.right.clone()),
+            }
+        }
+    }
+
+    // Whether `other` is small enough to insert entry by entry. Once m² ≤ n,
+    // m insertions cost O(m log n), within a constant of O(m log(n/m + 1)),
+    // and beat splitting on constant factors.
+    fn dwarfs(&self, other: &Tree<K,V,M>) -> bool {
+        other.len() <= self.len() / cmp::max(other.len(), 1)
+    }
+
+    fn union_with<F, C>(&self, other: &Tree<K,V,M>, f: &mut F, cmp: &C) -> Tree<K,V,M>
+        where F : FnMut(&K, &V, &V) -> V, C : Comparator<K> {
+        match other.0 {
+            None => self.clone(),
+            Some(_) if self.is_empty() => other.clone(),
+            Some(_) if self.dwarfs(other) => other.iter().fold(self.clone(), |t, (k, v)| {
+                let edit = t.modified(k.clone(), |old| Some(match old {
+                    Some(old) => f(k, old, v),
+                    None => v.clone(),
+                }), |_, _| false, cmp);
+                match edit {
+                    Edit::Unchanged => t,
+                    Edit::Replaced(t) | Edit::Inserted(t) | Edit::Removed(t) => t.to_black(),
+                }
+            }),
+            Some(ref node) => {
+                let (l, v, r) = self.split(&node.key, cmp);
+                let l = l.union_with(&node.left, f, cmp);
+                let r = r.union_with(&node.right, f, cmp);
+                let v = match v {
+                    Some(ref v) => f(&node.key, v, &node.value),
+                    None => node.value.clone(),
+                };
+                Tree::join(l, node.key.clone(), v, r)
+            }
+        }
+    }
+
+    // Keeps this tree's values, so subtrees shared with `other` are reused whole.
+    fn union<C>(&self, other: &Tree<K,V,M>, cmp: &C) -> Tree<K,V,M> where C : Comparator<K> {
+        match (&self.0, &other.0) {
+            (_, None) => self.clone(),
+            (None, _) => other.clone(),
+            (Some(a), Some(b)) if Rc::ptr_eq(a, b) => self.clone(),
+            _ if self.dwarfs(other) => other.iter().fold(self.clone(), |t, (k, v)| {
+                t.updated(k.clone(), v.clone(), false, cmp).to_black()
+            }),
+            (_, Some(node)) => {
+                let (l, v, r) = self.split(&node.key, cmp);
+                let l = l.union(&node.left, cmp);
+                let r = r.union(&node.right, cmp);
+                Tree::join(l, node.key.clone(), v.unwrap_or_else(|| node.value.clone()), r)
+            }
+        }
+    }
+
+    fn intersection<C>(&self, other: &Tree<K,V,M>, cmp: &C) -> Tree<K,V,M> where C : Comparator<K> {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) if Rc::ptr_eq(a, b) => self.clone(),
+            (Some(_), Some(node)) => {
+                let (l, v, r) = self.split(&node.key, cmp);
+                let l = l.intersection(&node.left, cmp);
+                let r = r.intersection(&node.right, cmp);
+                match v {
+                    Some(v) => Tree::join(l, node.key.clone(), v, r),
+                    None => Tree::concat(l, r),
+                }
+            },
+            _ => Tree(None),
+        }
+    }
+
+    fn intersection_with<F, C>(&self, other: &Tree<K,V,M>, f: &mut F, cmp: &C) -> Tree<K,V,M>
+        where F : FnMut(&K, &V, &V) -> V, C : Comparator<K> {
+        match other.0 {
+            Some(ref node) if !self.is_empty() => {
+                let (l, v, r) = self.split(&node.key, cmp);
+                let l = l.intersection_with(&node.left, f, cmp);
+                let r = r.intersection_with(&node.right, f, cmp);
+                match v {
+                    Some(ref v) => Tree::join(l, node.key.clone(), f(&node.key, v, &node.value), r),
+                    None => Tree::concat(l, r),
+                }
+            },
+            _ => Tree(None),
+        }
+    }
+
+    fn difference<C>(&self, other: &Tree<K,V,M>, cmp: &C) -> Tree<K,V,M> where C : Comparator<K> {
+        match (&self.0, &other.0) {
+            (None, _) => Tree(None),
+            (_, None) => self.clone(),
+            (Some(a), Some(b)) if Rc::ptr_eq(a, b) => Tree(None),
+            (_, Some(node)) => {
+                let (l, _, r) = self.split(&node.key, cmp);
+                Tree::concat(l.difference(&node.left, cmp), r.difference(&node.right, cmp))
+            },
+        }
+    }
+
+    fn symmetric_difference<C>(&self, other: &Tree<K,V,M>, cmp: &C) -> Tree<K,V,M> where C : Comparator<K> {
+        match (&self.0, &other.0) {
+            (None, _) => other.clone(),
+            (_, None) => self.clone(),
+            (Some(a), Some(b)) if Rc::ptr_eq(a, b) => Tree(None),
+            (_, Some(node)) => {
+                let (l, v, r) = self.split(&node.key, cmp);
+                let l = l.symmetric_difference(&node.left, cmp);
+                let r = r.symmetric_difference(&node.right, cmp);
+                match v {
+                    Some(_) => Tree::concat(l, r),
+                    None => Tree::join(l, node.key.clone(), node.value.clone(), r),
                 }
+            },
+        }
+    }
+}
+
+/// A violation of the red-black tree invariants, reported with the key of the
+/// node where it was found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InvariantError<K> {
+    /// The key is out of order with respect to one of its ancestors.
+    KeyOrder(K),
+    /// The node is red and has a red child.
+    RedRed(K),
+    /// The node is the root and is red.
+    RedRoot(K),
+    /// The node's subtrees contain different numbers of black nodes, or its
+    /// cached count of them is wrong.
+    BlackHeight(K),
+    /// The node's cached subtree size is wrong.
+    Size(K)
+}
+
+impl <K> Display for InvariantError<K> where K : Debug {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            InvariantError::KeyOrder(ref k) => write!(fmt, "key {:?} is out of order", k),
+            InvariantError::RedRed(ref k) => write!(fmt, "red node {:?} has a red child", k),
+            InvariantError::RedRoot(ref k) => write!(fmt, "root {:?} is red", k),
+            InvariantError::BlackHeight(ref k) => write!(fmt, "subtrees of {:?} have unequal black heights", k),
+            InvariantError::Size(ref k) => write!(fmt, "node {:?} has the wrong subtree size", k),
+        }
+    }
+}
+
+impl <K> Error for InvariantError<K> where K : Debug {}
+
+// The result of `Tree::modified`, telling each ancestor which rebalancing its
+// rebuilt child needs.
+enum Edit<K,V,M> where M : Measure<K,V> {
+    Unchanged,
+    Replaced(Tree<K,V,M>),
+    Inserted(Tree<K,V,M>),
+    Removed(Tree<K,V,M>)
+}
+
+// Rebalancing after an insertion, following Okasaki.
+mod insertion {
+    use super::{ Measure, Tree };
+
+    pub fn mk_tree<K : Clone, V : Clone, M : Measure<K,V>> 
+        (is_black: bool, z: K, zv: V, l: Tree<K,V,M>, r: Tree<K,V,M>) -> Tree<K,V,M>
+        {
+            if is_black {
+                Tree::black(z, zv, l, r)
+            } else {
+                Tree::red(z, zv, l, r)
+            }
+        }
+    pub fn balance_left<K : Clone, V : Clone, M : Measure<K,V>>
+        (is_black: bool, z: K, zv: V, l: Tree<K,V,M>, d: Tree<K,V,M>) -> Tree<K,V,M> 
+        {
+            if l.is_red() && l.left().is_red() {
+                Tree::red(l.key(), l.value(), 
+                          Tree::black(l.left().key(), l.left().value(), l.left().left(), l.left().right()),
+                          Tree::black(z, zv, l.right(), d))
+            } else if l.is_red() && l.right().is_red() {
+                Tree::red(l.right().key(), l.right().value(), 
+                          Tree::black(l.key(), l.value(), l.left(), l.right().left()),
+                          Tree::black(z, zv, l.right().right(), d))
+            } else {
+                mk_tree(is_black, z, zv, l, d)
+            }
         }
-        fn balance_right<K : Clone + Ord, V : Clone>
-            (x: K, xv: V, tl: Tree<K,V>, tr: Tree<K,V>) -> Tree<K,V> 
+    pub fn balance_right<K : Clone, V : Clone, M : Measure<K,V>>
+        (is_black: bool, x: K, xv: V, a: Tree<K,V,M>, r: Tree<K,V,M>) -> Tree<K,V,M>
         {
+            if r.is_red() && r.left().is_red() {
+                Tree::red(r.left().key(), r.left().value(),
+                Tree::black(x, xv, a, r.left().left()),
+                Tree::black(r.key(), r.value(), r.left().right(), r.right()))
+            } else if r.is_red() && r.right().is_red() {
+                Tree::red(r.key(), r.value(),
+                Tree::black(x, xv, a, r.left()),
+                Tree::black(r.right().key(), r.right().value(), r.right().left(), r.right().right()))
+            } else {
+                mk_tree(is_black, x, xv, a, r)
+            }
+        }
+}
+
+// Rebalancing after a deletion, following Kahrs.
+mod deletion {
+    use super::{ Measure, Tree };
+
+    pub fn balance<K : Clone, V : Clone, M : Measure<K,V>>(
+        x: K, xv: V, tl: Tree<K,V,M>, tr: Tree<K,V,M>) -> Tree<K,V,M> {
+        if tl.is_red() {
             if tr.is_red() {
-                Tree::red(x, xv, tl, tr.to_black())
-            } else if tl.is_black() {
-                balance(x, xv, tl.to_red(), tr)
-            } else if tl.is_red() && tl.right().is_black() {
+                Tree::red(x, xv, 
+                          tl.to_black(),
+                          tr.to_black())
+            } else if tl.left().is_red() {
+                Tree::red(tl.key(), tl.value(), 
+                          tl.left().to_black(), 
+                          Tree::black(x, xv, tl.right(), tr))
+            } else if tl.right().is_red() {
                 Tree::red(tl.right().key(), tl.right().value(), 
-                          balance(tl.key(), tl.value(), subl(tl.left()), tl.right().left()),
+                          Tree::black(tl.key(), tl.value(), tl.left(), tl.right().left()), 
                           Tree::black(x, xv, tl.right().right(), tr))
             } else {
-                unreachable!()
+                Tree::black(x, xv, tl, tr)
+            }
+        } else if tr.is_red() {
+            if tr.right().is_red() {
+                Tree::red(tr.key(), tr.value(),
+                    Tree::black(x, xv, tl, tr.left()),
+                    tr.right().to_black())
+            } else if tr.left().is_red() {
+                Tree::red(tr.left().key(), tr.left().value(),
+                    Tree::black(x, xv, tl, tr.left().left()),
+                    Tree::black(tr.key(), tr.value(), tr.left().right(), tr.right()))
+            } else {
+                Tree::black(x, xv, tl, tr)
             }
+        } else {
+            Tree::black(x, xv, tl, tr)
         }
+    }
 
-        fn append<K : Clone + Ord, V : Clone>(tl: Tree<K,V>, tr: Tree<K,V>) -> Tree<K,V> {
-            if tl.is_empty() {
-                tr
-            } else if tr.is_empty() {
-                tl
-            } else if tl.is_red() && tr.is_red() {
-                let bc = append(tl.right(), tr.left());
-                if bc.is_red() {
-                    Tree::red(bc.key(), bc.value(), 
-                              Tree::red(tl.key(), tl.value(), tl.left(), bc.left()),
-                              Tree::red(tr.key(), tr.value(), bc.right(), tr.right()))
-                } else {
-                    Tree::red(tl.key(), tl.value(), tl.left(), Tree::red(tr.key(), tr.value(), bc, tr.right()))
-                }
-            } else if tl.is_black() && tr.is_black() {
-                let bc = append(tl.right(), tr.left());
-                if bc.is_red() {
-                    Tree::red(bc.key(), bc.value(), 
-                              Tree::black(tl.key(), tl.value(), tl.left(), bc.left()),
-                              Tree::black(tr.key(), tr.value(), bc.right(), tr.right()))
-                } else {
-                    balance_left(tl.key(), tl.value(), tl.left(), Tree::black(tr.key(), tr.value(), bc, tr.right()))
-                }
-            } else if tr.is_red() {
-                Tree::red(tr.key(), tr.value(), append(tl, tr.left()), tr.right())
-            } else if tl.is_red() {
-                Tree::red(tl.key(), tl.value(), tl.left(), append(tl.right(), tr))
+    pub fn subl<K : Clone, V : Clone, M : Measure<K,V>>(t: Tree<K,V,M>)  -> Tree<K,V,M> {
+        assert!(t.is_black_node());
+        t.to_red()
+    }
+
+    pub fn balance_left<K : Clone, V : Clone, M : Measure<K,V>>
+        (x: K, xv: V, tl: Tree<K,V,M>, tr: Tree<K,V,M>) -> Tree<K,V,M> 
+    {
+            if tl.is_red() {
+                Tree::red(x, xv, tl.to_black(), tr)
+            } else if tr.is_black_node() {
+                balance(x, xv, tl, tr.to_red())
+            } else if tr.is_red() && tr.left().is_black_node() {
+                Tree::red(tr.left().key(), tr.left().value(),
+                    Tree::black(x, xv, tl, tr.left().left()),
+                    balance(tr.key(), tr.value(), tr.left().right(), subl(tr.right())))
             } else {
                 unreachable!()
             }
+    }
+    pub fn balance_right<K : Clone, V : Clone, M : Measure<K,V>>
+        (x: K, xv: V, tl: Tree<K,V,M>, tr: Tree<K,V,M>) -> Tree<K,V,M> 
+    {
+        if tr.is_red() {
+            Tree::red(x, xv, tl, tr.to_black())
+        } else if tl.is_black_node() {
+            balance(x, xv, tl.to_red(), tr)
+        } else if tl.is_red() && tl.right().is_black_node() {
+            Tree::red(tl.right().key(), tl.right().value(), 
+                      balance(tl.key(), tl.value(), subl(tl.left()), tl.right().left()),
+                      Tree::black(x, xv, tl.right().right(), tr))
+        } else {
+            unreachable!()
         }
+    }
 
-        if self.is_empty() {
-            Tree(None)
+    pub fn append<K : Clone, V : Clone, M : Measure<K,V>>(tl: Tree<K,V,M>, tr: Tree<K,V,M>) -> Tree<K,V,M> {
+        if tl.is_empty() {
+            tr
+        } else if tr.is_empty() {
+            tl
+        } else if tl.is_red() && tr.is_red() {
+            let bc = append(tl.right(), tr.left());
+            if bc.is_red() {
+                Tree::red(bc.key(), bc.value(), 
+                          Tree::red(tl.key(), tl.value(), tl.left(), bc.left()),
+                          Tree::red(tr.key(), tr.value(), bc.right(), tr.right()))
+            } else {
+                Tree::red(tl.key(), tl.value(), tl.left(), Tree::red(tr.key(), tr.value(), bc, tr.right()))
+            }
+        } else if tl.is_black() && tr.is_black() {
+            let bc = append(tl.right(), tr.left());
+            if bc.is_red() {
+                Tree::red(bc.key(), bc.value(), 
+                          Tree::black(tl.key(), tl.value(), tl.left(), bc.left()),
+                          Tree::black(tr.key(), tr.value(), bc.right(), tr.right()))
+            } else {
+                balance_left(tl.key(), tl.value(), tl.left(), Tree::black(tr.key(), tr.value(), bc, tr.right()))
+            }
+        } else if tr.is_red() {
+            Tree::red(tr.key(), tr.value(), append(tl, tr.left()), tr.right())
+        } else if tl.is_red() {
+            Tree::red(tl.key(), tl.value(), tl.left(), append(tl.right(), tr))
         } else {
-            match k.cmp(&self.key()) {
-                Ordering::Less =>
-                    if self.left().is_black() {
-                        balance_left(self.key(), self.value(), self.left().removed(k), self.right()) 
-                    } else {
-                        Tree::red(self.key(), self.value(), self.left().removed(k), self.right())
-                    },
-                Ordering::Greater =>
-                    if self.right().is_black() {
-                        balance_right(self.key(), self.value(), self.left(), self.right().removed(k))
-                    } else {
-                        Tree::red(self.key(), self.value(), self.left(), self.right().removed(k))
-                    },
-                Ordering::Equal => append(self.left(), self.right())
+            unreachable!()
+        }
+    }
+}
+
+impl <'a, K, V, M> IntoIterator for &'a Tree<K,V,M> where K : Clone, V : Clone, M : Measure<K,V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, M>;
+    fn into_iter(self) -> Iter<'a, K, V, M> {
+        self.iter()
+    }
+}
+
+// The front and back stacks each hold the path to the next node they will
+// yield; iteration is finished once either end yields the node at the top of
+// the other's stack.
+pub struct Iter<'a, K, V, M = ()>
+where K : 'a, V : 'a, M : 'a + Measure<K,V> {
+    front: Vec<&'a Node<K,V,M>>,
+    back: Vec<&'a Node<K,V,M>>,
+    done: bool
+}
+
+impl <'a, K, V, M> Iter<'a, K, V, M> where M : Measure<K,V> {
+    fn empty() -> Iter<'a, K, V, M> {
+        Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            done: false
+        }
+    }
+
+    fn seek_front<Q, C>(&mut self, mut tree: &'a Tree<K,V,M>, bound: Bound<&Q>, cmp: &C)
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        while let Some(ref node) = tree.0 {
+            let key: &Q = node.key.borrow();
+            let in_range = match bound {
+                Bound::Included(lo) => cmp.compare(key, lo) != Ordering::Less,
+                Bound::Excluded(lo) => cmp.compare(key, lo) == Ordering::Greater,
+                Bound::Unbounded => true,
+            };
+            if in_range {
+                self.front.push(node);
+                tree = &node.left;
+            } else {
+                tree = &node.right;
             }
         }
     }
 
-    fn lookup(&self, k: K) -> Self {
-        let mut tree = self.clone();
-        while !tree.is_empty() {
-            match k.cmp(&tree.key()) {
-                Ordering::Less => tree = tree.left(),
-                Ordering::Greater => tree = tree.right(),
-                Ordering::Equal => break,
+    fn seek_back<Q, C>(&mut self, mut tree: &'a Tree<K,V,M>, bound: Bound<&Q>, cmp: &C)
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        while let Some(ref node) = tree.0 {
+            let key: &Q = node.key.borrow();
+            let in_range = match bound {
+                Bound::Included(hi) => cmp.compare(key, hi) != Ordering::Greater,
+                Bound::Excluded(hi) => cmp.compare(key, hi) == Ordering::Less,
+                Bound::Unbounded => true,
+            };
+            if in_range {
+                self.back.push(node);
+                tree = &node.right;
+            } else {
+                tree = &node.left;
             }
         }
-        tree
     }
 
-    pub fn contains(&self, k: K) -> bool {
-        !self.lookup(k).is_empty()
+    fn push_left(&mut self, mut tree: &'a Tree<K,V,M>) {
+        while let Some(ref node) = tree.0 {
+            self.front.push(node);
+            tree = &node.left;
+        }
     }
 
-    pub fn get(&self, k: K) -> Option<V> {
-        self.lookup(k).0.map(|n| n.value.clone())
+    fn push_right(&mut self, mut tree: &'a Tree<K,V,M>) {
+        while let Some(ref node) = tree.0 {
+            self.back.push(node);
+            tree = &node.right;
+        }
     }
-}
+
+    fn met(node: &'a Node<K,V,M>, other: &[&'a Node<K,V,M>]) -> bool {
+        other.last().is_some_and(|o| ptr::eq(node, *o))
+    }
+}
+
+impl <'a, K, V, M> Iterator for Iter<'a, K, V, M> where M : Measure<K,V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.done {
+            return None;
+        }
+        let node = self.front.pop()?;
+        self.done = Iter::met(node, &self.back);
+        self.push_left(&node.right);
+        Some((&node.key, &node.value))
+    }
+}
+
+impl <'a, K, V, M> DoubleEndedIterator for Iter<'a, K, V, M> where M : Measure<K,V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.done {
+            return None;
+        }
+        let node = self.back.pop()?;
+        self.done = Iter::met(node, &self.front);
+        self.push_right(&node.left);
+        Some((&node.key, &node.value))
+    }
+}
+
+pub struct Keys<'a, K, V, M = ()>(Iter<'a, K, V, M>)
+where K : 'a, V : 'a, M : 'a + Measure<K,V>;
+
+impl <'a, K, V, M> Iterator for Keys<'a, K, V, M> where M : Measure<K,V> {
+    type Item = &'a K;
+    fn next(&mut self) -> Option<&'a K> {
+        self.0.next().map(|(k, _)| k)
+    }
+}
+
+impl <'a, K, V, M> DoubleEndedIterator for Keys<'a, K, V, M> where M : Measure<K,V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.0.next_back().map(|(k, _)| k)
+    }
+}
+
+pub struct Values<'a, K, V, M = ()>(Iter<'a, K, V, M>)
+where K : 'a, V : 'a, M : 'a + Measure<K,V>;
+
+impl <'a, K, V, M> Iterator for Values<'a, K, V, M> where M : Measure<K,V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<&'a V> {
+        self.0.next().map(|(_, v)| v)
+    }
+}
+
+impl <'a, K, V, M> DoubleEndedIterator for Values<'a, K, V, M> where M : Measure<K,V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.0.next_back().map(|(_, v)| v)
+    }
+}
+
+pub struct OrdMap<K, V, C = Natural, M = ()> where M : Measure<K,V> {
+    tree: Tree<K,V,M>,
+    cmp: C
+}
+
+impl <K,V,C,M> Clone for OrdMap<K,V,C,M> where C : Clone, M : Measure<K,V> {
+    fn clone(&self) -> Self {
+        OrdMap { tree: self.tree.clone(), cmp: self.cmp.clone() }
+    }
+}
+
+impl <K,V> OrdMap<K,V> where K : Clone + Ord, V : Clone {
+    pub fn new() -> OrdMap<K,V> {
+        OrdMap::with_comparator(Natural)
+    }
+
+    /// Builds a map in linear time from entries whose keys are strictly
+    /// increasing. Panics if they are not.
+    pub fn from_sorted_iter<I>(iter: I) -> OrdMap<K,V> where I : IntoIterator<Item=(K, V)> {
+        OrdMap::from_sorted_iter_with(iter, Natural)
+    }
+}
+
+impl <K,V,C> OrdMap<K,V,C> where K : Clone, V : Clone, C : Comparator<K> + Clone {
+    pub fn with_comparator(cmp: C) -> OrdMap<K,V,C> {
+        OrdMap::measured(cmp)
+    }
+
+    /// Like `from_sorted_iter`, with keys strictly increasing under `cmp`.
+    pub fn from_sorted_iter_with<I>(iter: I, cmp: C) -> OrdMap<K,V,C> where I : IntoIterator<Item=(K, V)> {
+        OrdMap::from_sorted_iter_measured(iter, cmp)
+    }
+}
+
+impl <K,V,C,M> OrdMap<K,V,C,M> where K : Clone, V : Clone, C : Comparator<K> + Clone, M : Measure<K,V> {
+    /// An empty map ordered by `cmp` whose subtrees cache summaries under `M`.
+    pub fn measured(cmp: C) -> OrdMap<K,V,C,M> {
+        OrdMap { tree: Tree(None), cmp }
+    }
+
+    /// Like `from_sorted_iter_with`, caching summaries under `M`.
+    pub fn from_sorted_iter_measured<I>(iter: I, cmp: C) -> OrdMap<K,V,C,M> where I : IntoIterator<Item=(K, V)> {
+        let tree = Tree::from_sorted(iter.into_iter().collect(), &cmp);
+        OrdMap { tree: tree.to_black(), cmp }
+    }
+
+    fn wrap(&self, tree: Tree<K,V,M>) -> OrdMap<K,V,C,M> {
+        OrdMap { tree: tree.to_black(), cmp: self.cmp.clone() }
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn insert(&self, key: K, value: V) -> OrdMap<K,V,C,M> {
+        self.wrap(self.tree.updated(key, value, true, &self.cmp))
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> OrdMap<K,V,C,M> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        match self.tree.removed(key, &self.cmp) {
+            Some(tree) => self.wrap(tree),
+            None => self.clone(),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.get(key, &self.cmp)
+    }
+
+    /// The stored key equal to `key`, along with its value.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.lookup(key, &self.cmp).map(|n| (&n.key, &n.value))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.contains(key, &self.cmp)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// The number of keys in the map that are less than `key`.
+    pub fn rank<Q>(&self, key: &Q) -> usize where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.rank(key, &self.cmp)
+    }
+
+    /// The entry with the `index`th smallest key, counting from zero.
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        self.tree.select(index)
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.tree.first()
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.tree.last()
+    }
+
+    /// The entry with the greatest key not greater than `key`.
+    pub fn floor<Q>(&self, key: &Q) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.floor(key, &self.cmp)
+    }
+
+    /// The entry with the least key not less than `key`.
+    pub fn ceiling<Q>(&self, key: &Q) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.ceiling(key, &self.cmp)
+    }
+
+    /// The entry with the greatest key less than `key`.
+    pub fn lower<Q>(&self, key: &Q) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.lower(key, &self.cmp)
+    }
+
+    /// The entry with the least key greater than `key`.
+    pub fn higher<Q>(&self, key: &Q) -> Option<(&K, &V)> where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.tree.higher(key, &self.cmp)
+    }
+
+    /// The entry with the smallest key and the map without it.
+    pub fn pop_first(&self) -> Option<((K, V), Self)> {
+        self.tree.pop_first(&self.cmp).map(|(e, tree)| (e, self.wrap(tree)))
+    }
+
+    /// The entry with the largest key and the map without it.
+    pub fn pop_last(&self) -> Option<((K, V), Self)> {
+        self.tree.pop_last(&self.cmp).map(|(e, tree)| (e, self.wrap(tree)))
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V, M> {
+        self.tree.iter()
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V, M> {
+        self.tree.keys()
+    }
+
+    pub fn values(&self) -> Values<'_, K, V, M> {
+        self.tree.values()
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V, M>
+        where K : Borrow<Q>, Q : ?Sized, R : RangeBounds<Q>, C : Comparator<Q> {
+        self.tree.range(range, &self.cmp)
+    }
+
+    /// The summary of every entry in the map, in O(1).
+    pub fn summary(&self) -> M::Summary {
+        self.tree.summary()
+    }
+
+    /// The summary of the entries with keys in `range`, in O(log n).
+    pub fn range_summary<Q, R>(&self, range: R) -> M::Summary
+        where K : Borrow<Q>, Q : ?Sized, R : RangeBounds<Q>, C : Comparator<Q> {
+        self.tree.summarize(range.start_bound(), range.end_bound(), &self.cmp)
+    }
+
+    /// A cursor positioned at the first entry.
+    pub fn cursor(&self) -> Cursor<'_, K, V, C, M> {
+        let mut cursor = Cursor { map: self, path: Vec::new() };
+        cursor.move_next();
+        cursor
+    }
+
+    /// Inserts, replaces or removes the entry for `key` according to `f`,
+    /// which is passed the current value, if any. Returns a map sharing this
+    /// map's root if the key is absent and `f` returns `None`; see
+    /// `update_with_eq` for values that are left unchanged.
+    pub fn update_with<F>(&self, key: K, f: F) -> OrdMap<K,V,C,M> where F : FnOnce(Option<&V>) -> Option<V> {
+        self.modified(key, f, |_, _| false)
+    }
+
+    /// Like `update_with`, but also returns a map sharing this map's root if
+    /// `f` returns a value equal to the current one, which is then kept.
+    pub fn update_with_eq<F>(&self, key: K, f: F) -> OrdMap<K,V,C,M>
+        where F : FnOnce(Option<&V>) -> Option<V>, V : PartialEq {
+        self.modified(key, f, |old, new| old == new)
+    }
+
+    fn modified<F, S>(&self, key: K, f: F, same: S) -> OrdMap<K,V,C,M>
+        where F : FnOnce(Option<&V>) -> Option<V>, S : FnOnce(&V, &V) -> bool {
+        match self.tree.modified(key, f, same, &self.cmp) {
+            Edit::Unchanged => self.clone(),
+            Edit::Replaced(tree) | Edit::Inserted(tree) | Edit::Removed(tree) => self.wrap(tree),
+        }
+    }
+
+    /// Verifies the ordering, coloring and black height invariants of the
+    /// underlying red-black tree.
+    pub fn check_invariants(&self) -> Result<(), InvariantError<K>> {
+        if self.tree.is_red() {
+            return Err(InvariantError::RedRoot(self.tree.key()));
+        }
+        self.tree.check(None, None, &self.cmp).map(|_| ())
+    }
+
+    /// Splits the map into the entries with keys less than and greater than
+    /// `key`, along with the value stored at `key` itself.
+    pub fn split<Q>(&self, key: &Q) -> (Self, Option<V>, Self)
+        where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let (l, v, r) = self.tree.split(key, &self.cmp);
+        (self.wrap(l), v, self.wrap(r))
+    }
+}
+
+// Operations combining two maps, which must be ordered the same way.
+impl <K,V,C,M> OrdMap<K,V,C,M>
+    where K : Clone, V : Clone, C : Comparator<K> + Clone + PartialEq, M : Measure<K,V> {
+    fn same_order(&self, other: &OrdMap<K,V,C,M>) {
+        assert!(self.cmp == other.cmp, "Tried to combine maps with different comparators");
+    }
+
+    /// The inverse of `split`. Every key in `left` must be less than `key` and
+    /// every key in `right` greater than it.
+    pub fn join(left: &OrdMap<K,V,C,M>, key: K, value: V, right: &OrdMap<K,V,C,M>) -> OrdMap<K,V,C,M> {
+        left.same_order(right);
+        let cmp = &left.cmp;
+        assert!(left.tree.last().is_none_or(|(k, _)| cmp.compare(k, &key) == Ordering::Less),
+                "Tried to join a map with keys not less than the separating key");
+        assert!(right.tree.first().is_none_or(|(k, _)| cmp.compare(k, &key) == Ordering::Greater),
+                "Tried to join a map with keys not greater than the separating key");
+        left.wrap(Tree::join(left.tree.clone(), key, value, right.tree.clone()))
+    }
+
+    /// Entries from both maps; `f` is passed the key, this map's value and
+    /// `other`'s value for keys present in both.
+    pub fn union_with<F>(&self, other: &OrdMap<K,V,C,M>, mut f: F) -> OrdMap<K,V,C,M>
+        where F : FnMut(&K, &V, &V) -> V {
+        self.same_order(other);
+        self.wrap(self.tree.union_with(&other.tree, &mut f, &self.cmp))
+    }
+
+    /// Entries from both maps, preferring this map's values.
+    pub fn union(&self, other: &OrdMap<K,V,C,M>) -> OrdMap<K,V,C,M> {
+        self.same_order(other);
+        self.wrap(self.tree.union(&other.tree, &self.cmp))
+    }
+
+    /// Entries of this map whose keys are also in `other`.
+    pub fn intersection(&self, other: &OrdMap<K,V,C,M>) -> OrdMap<K,V,C,M> {
+        self.same_order(other);
+        self.wrap(self.tree.intersection(&other.tree, &self.cmp))
+    }
+
+    /// Entries whose keys are in both maps; `f` is passed the key, this map's
+    /// value and `other`'s value.
+    pub fn intersection_with<F>(&self, other: &OrdMap<K,V,C,M>, mut f: F) -> OrdMap<K,V,C,M>
+        where F : FnMut(&K, &V, &V) -> V {
+        self.same_order(other);
+        self.wrap(self.tree.intersection_with(&other.tree, &mut f, &self.cmp))
+    }
+
+    /// Entries of this map whose keys are not in `other`.
+    pub fn difference(&self, other: &OrdMap<K,V,C,M>) -> OrdMap<K,V,C,M> {
+        self.same_order(other);
+        self.wrap(self.tree.difference(&other.tree, &self.cmp))
+    }
+
+    /// Entries whose keys are in exactly one of the two maps.
+    pub fn symmetric_difference(&self, other: &OrdMap<K,V,C,M>) -> OrdMap<K,V,C,M> {
+        self.same_order(other);
+        self.wrap(self.tree.symmetric_difference(&other.tree, &self.cmp))
+    }
+
+    /// The entries added, removed or changed going from `old` to `new`, in
+    /// key order. Subtrees shared by both versions are skipped unvisited.
+    pub fn diff<'a>(old: &'a OrdMap<K,V,C,M>, new: &'a OrdMap<K,V,C,M>) -> Diff<'a, K, V, C, M> where V : PartialEq {
+        old.same_order(new);
+        Diff::new(&old.tree, &new.tree, &old.cmp)
+    }
+}
+
+impl <K,V,C,M> Default for OrdMap<K,V,C,M>
+    where K : Clone, V : Clone, C : Comparator<K> + Clone + Default, M : Measure<K,V> {
+    fn default() -> OrdMap<K,V,C,M> {
+        OrdMap::measured(C::default())
+    }
+}
+
+impl <K,V,C,M> FromIterator<(K, V)> for OrdMap<K,V,C,M>
+    where K : Clone, V : Clone, C : Comparator<K> + Clone + Default, M : Measure<K,V> {
+    fn from_iter<T>(iter: T) -> Self where T : IntoIterator<Item=(K, V)> {
+        let cmp = C::default();
+        let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+        entries.sort_by(|a, b| cmp.compare(&a.0, &b.0));
+        // Keep the last value given for each key, as repeated inserts would.
+        entries.dedup_by(|next, kept| {
+            if cmp.compare(&next.0, &kept.0) == Ordering::Equal {
+                mem::swap(next, kept);
+                true
+            } else {
+                false
+            }
+        });
+        OrdMap::from_sorted_iter_measured(entries, cmp)
+    }
+}
+
+impl <'a, K, V, C, M> IntoIterator for &'a OrdMap<K,V,C,M>
+    where K : Clone, V : Clone, C : Comparator<K> + Clone, M : Measure<K,V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, M>;
+    fn into_iter(self) -> Iter<'a, K, V, M> {
+        self.iter()
+    }
+}
+
+/// A difference between two versions of a map, as yielded by `OrdMap::diff`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change<'a, K : 'a, V : 'a> {
+    Added(&'a K, &'a V),
+    Removed(&'a K, &'a V),
+    Changed(&'a K, &'a V, &'a V)
+}
+
+// A part of one version still to be compared: a whole subtree, or the entry
+// of a node whose left subtree has already been compared.
+enum Pending<'a, K : 'a, V : 'a, M : 'a + Measure<K,V>> {
+    Subtree(&'a Tree<K,V,M>),
+    Entry(&'a Node<K,V,M>)
+}
+
+impl <'a, K, V, M> Pending<'a, K, V, M> where M : Measure<K,V> {
+    fn len(&self) -> usize {
+        match *self {
+            Pending::Subtree(tree) => tree.0.as_ref().map_or(0, |n| n.size),
+            Pending::Entry(_) => 1,
+        }
+    }
+}
+
+pub struct Diff<'a, K, V, C, M = ()>
+where K : 'a, V : 'a, C : 'a, M : 'a + Measure<K,V> {
+    old: Vec<Pending<'a, K, V, M>>,
+    new: Vec<Pending<'a, K, V, M>>,
+    cmp: &'a C
+}
+
+impl <'a, K, V, C, M> Diff<'a, K, V, C, M> where M : Measure<K,V> {
+    fn new(old: &'a Tree<K,V,M>, new: &'a Tree<K,V,M>, cmp: &'a C) -> Diff<'a, K, V, C, M> {
+        Diff { old: vec![Pending::Subtree(old)], new: vec![Pending::Subtree(new)], cmp }
+    }
+
+    // Replaces a subtree on top of the stack by its left subtree, entry and
+    // right subtree. Returns false if the top is an entry.
+    fn expand(stack: &mut Vec<Pending<'a, K, V, M>>) -> bool {
+        match stack.last() {
+            Some(&Pending::Subtree(tree)) => {
+                stack.pop();
+                if let Some(ref node) = tree.0 {
+                    stack.push(Pending::Subtree(&node.right));
+                    stack.push(Pending::Entry(node));
+                    stack.push(Pending::Subtree(&node.left));
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+impl <'a, K, V, C, M> Iterator for Diff<'a, K, V, C, M> where V : PartialEq, C : Comparator<K>, M : Measure<K,V> {
+    type Item = Change<'a, K, V>;
+    fn next(&mut self) -> Option<Change<'a, K, V>> {
+        loop {
+            let (old, new) = match (self.old.last(), self.new.last()) {
+                (None, None) => return None,
+                (Some(&Pending::Subtree(a)), Some(&Pending::Subtree(b))) if shared(a, b) => {
+                    self.old.pop();
+                    self.new.pop();
+                    continue;
+                },
+                (Some(&Pending::Entry(a)), Some(&Pending::Entry(b))) => (a, b),
+                (Some(&Pending::Entry(a)), None) => {
+                    self.old.pop();
+                    return Some(Change::Removed(&a.key, &a.value));
+                },
+                (None, Some(&Pending::Entry(b))) => {
+                    self.new.pop();
+                    return Some(Change::Added(&b.key, &b.value));
+                },
+                (a, b) => {
+                    // Expand the larger side first, so that a subtree shared
+                    // at different depths is reached on both sides.
+                    let old_first = a.map_or(0, Pending::len) >= b.map_or(0, Pending::len);
+                    let (first, second) = if old_first {
+                        (&mut self.old, &mut self.new)
+                    } else {
+                        (&mut self.new, &mut self.old)
+                    };
+                    if !Self::expand(first) {
+                        Self::expand(second);
+                    }
+                    continue;
+                },
+            };
+            match self.cmp.compare(&old.key, &new.key) {
+                Ordering::Less => {
+                    self.old.pop();
+                    return Some(Change::Removed(&old.key, &old.value));
+                },
+                Ordering::Greater => {
+                    self.new.pop();
+                    return Some(Change::Added(&new.key, &new.value));
+                },
+                Ordering::Equal => {
+                    self.old.pop();
+                    self.new.pop();
+                    if !ptr::eq(old, new) && old.value != new.value {
+                        return Some(Change::Changed(&old.key, &old.value, &new.value));
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn shared<K, V, M>(a: &Tree<K,V,M>, b: &Tree<K,V,M>) -> bool where M : Measure<K,V> {
+    match (&a.0, &b.0) {
+        (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// A position within a map that can seek to a key and step in either
+/// direction. Past either end the cursor rests on a ghost position between
+/// the last entry and the first, holding no entry.
+pub struct Cursor<'a, K, V, C, M = ()>
+where K : 'a, V : 'a, C : 'a, M : 'a + Measure<K,V> {
+    map: &'a OrdMap<K,V,C,M>,
+    // The nodes from the root down to the current one; empty at the ghost.
+    path: Vec<&'a Node<K,V,M>>
+}
+
+impl <'a, K, V, C, M> Cursor<'a, K, V, C, M>
+    where K : Clone, V : Clone, C : Comparator<K> + Clone, M : Measure<K,V> {
+    pub fn key(&self) -> Option<&'a K> {
+        self.path.last().map(|n| &n.key)
+    }
+
+    pub fn value(&self) -> Option<&'a V> {
+        self.path.last().map(|n| &n.value)
+    }
+
+    pub fn entry(&self) -> Option<(&'a K, &'a V)> {
+        self.path.last().map(|n| (&n.key, &n.value))
+    }
+
+    /// Moves to `key`, returning whether it is present. If it is not, the
+    /// cursor moves to the ghost position.
+    pub fn seek<Q>(&mut self, key: &Q) -> bool where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.seek_at_or_after(key);
+        let found = self.key().is_some_and(|k| self.map.cmp.compare(k.borrow(), key) == Ordering::Equal);
+        if !found {
+            self.path.clear();
+        }
+        found
+    }
+
+    /// Moves to the least key not less than `key`, or to the ghost position
+    /// if there is none.
+    pub fn seek_at_or_after<Q>(&mut self, key: &Q) where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.seek_toward(key, Ordering::Greater)
+    }
+
+    /// Moves to the greatest key not greater than `key`, or to the ghost
+    /// position if there is none.
+    pub fn seek_at_or_before<Q>(&mut self, key: &Q) where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.seek_toward(key, Ordering::Less)
+    }
+
+    // Descends towards `key`, remembering the last node on the `toward` side
+    // of it, and stops there unless `key` itself is found.
+    fn seek_toward<Q>(&mut self, key: &Q, toward: Ordering) where K : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let map = self.map;
+        self.path.clear();
+        let mut keep = 0;
+        let mut tree = &map.tree;
+        while let Some(ref node) = tree.0 {
+            self.path.push(node);
+            let ord = map.cmp.compare(node.key.borrow(), key);
+            if ord == Ordering::Equal {
+                return;
+            }
+            let (near, far) = if toward == Ordering::Greater {
+                (&node.left, &node.right)
+            } else {
+                (&node.right, &node.left)
+            };
+            if ord == toward {
+                keep = self.path.len();
+                tree = near;
+            } else {
+                tree = far;
+            }
+        }
+        self.path.truncate(keep);
+    }
+
+    /// Steps to the next entry and returns it. Stepping past the last entry
+    /// moves to the ghost position; stepping from there moves to the first.
+    pub fn move_next(&mut self) -> Option<(&'a K, &'a V)> {
+        match self.path.last() {
+            None => self.push_leftmost(&self.map.tree),
+            Some(node) if !node.right.is_empty() => self.push_leftmost(&node.right),
+            Some(_) => self.climb(|parent, child| parent.left.is_node(child)),
+        }
+        self.entry()
+    }
+
+    /// Steps to the previous entry and returns it. Stepping before the first
+    /// entry moves to the ghost position; stepping from there moves to the last.
+    pub fn move_prev(&mut self) -> Option<(&'a K, &'a V)> {
+        match self.path.last() {
+            None => self.push_rightmost(&self.map.tree),
+            Some(node) if !node.left.is_empty() => self.push_rightmost(&node.left),
+            Some(_) => self.climb(|parent, child| parent.right.is_node(child)),
+        }
+        self.entry()
+    }
+
+    fn push_leftmost(&mut self, mut tree: &'a Tree<K,V,M>) {
+        while let Some(ref node) = tree.0 {
+            self.path.push(node);
+            tree = &node.left;
+        }
+    }
+
+    fn push_rightmost(&mut self, mut tree: &'a Tree<K,V,M>) {
+        while let Some(ref node) = tree.0 {
+            self.path.push(node);
+            tree = &node.right;
+        }
+    }
+
+    // Climbs until reaching the child of a parent for which `arrived` holds,
+    // stopping at that parent.
+    fn climb<F>(&mut self, arrived: F) where F : Fn(&Node<K,V,M>, &Node<K,V,M>) -> bool {
+        while let Some(child) = self.path.pop() {
+            match self.path.last() {
+                Some(parent) if arrived(parent, child) => return,
+                _ => {},
+            }
+        }
+    }
+
+    /// The map with the value under the cursor replaced by `value`, or this
+    /// cursor's map at the ghost position.
+    pub fn replace(&self, value: V) -> OrdMap<K,V,C,M> {
+        match self.key() {
+            Some(key) => self.map.insert(key.clone(), value),
+            None => self.map.clone(),
+        }
+    }
+
+    /// The map without the entry under the cursor, or this cursor's map at
+    /// the ghost position.
+    pub fn remove(&self) -> OrdMap<K,V,C,M> {
+        match self.key() {
+            Some(key) => self.map.remove(key),
+            None => self.map.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OrdSet<T, C = Natural> {
+    map: OrdMap<T,(),C>
+}
+
+impl <T> OrdSet<T> where T : Clone + Ord {
+    pub fn new() -> OrdSet<T> {
+        OrdSet { map: OrdMap::new() }
+    }
+
+    /// Builds a set in linear time from strictly increasing values. Panics if
+    /// they are not.
+    pub fn from_sorted_iter<I>(iter: I) -> OrdSet<T> where I : IntoIterator<Item=T> {
+        OrdSet { map: OrdMap::from_sorted_iter(iter.into_iter().map(|t| (t, ()))) }
+    }
+}
+
+impl <T,C> OrdSet<T,C> where T : Clone, C : Comparator<T> + Clone {
+    pub fn with_comparator(cmp: C) -> OrdSet<T,C> {
+        OrdSet { map: OrdMap::with_comparator(cmp) }
+    }
+
+    pub fn from_sorted_iter_with<I>(iter: I, cmp: C) -> OrdSet<T,C> where I : IntoIterator<Item=T> {
+        OrdSet { map: OrdMap::from_sorted_iter_with(iter.into_iter().map(|t| (t, ())), cmp) }
+    }
+
+    pub fn comparator(&self) -> &C {
+        self.map.comparator()
+    }
+
+    pub fn insert(&self, value: T) -> OrdSet<T,C> {
+        OrdSet { map: self.map.update_with_eq(value, |_| Some(())) }
+    }
+
+    pub fn remove<Q>(&self, value: &Q) -> OrdSet<T,C> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        OrdSet { map: self.map.remove(value) }
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.contains_key(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn rank<Q>(&self, value: &Q) -> usize where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.rank(value)
+    }
+
+    pub fn select(&self, index: usize) -> Option<&T> {
+        self.map.select(index).map(|(t, _)| t)
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(t, _)| t)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(t, _)| t)
+    }
+
+    pub fn floor<Q>(&self, value: &Q) -> Option<&T> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.floor(value).map(|(t, _)| t)
+    }
+
+    pub fn ceiling<Q>(&self, value: &Q) -> Option<&T> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.ceiling(value).map(|(t, _)| t)
+    }
+
+    pub fn lower<Q>(&self, value: &Q) -> Option<&T> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.lower(value).map(|(t, _)| t)
+    }
+
+    pub fn higher<Q>(&self, value: &Q) -> Option<&T> where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        self.map.higher(value).map(|(t, _)| t)
+    }
+
+    pub fn pop_first(&self) -> Option<(T, OrdSet<T,C>)> {
+        self.map.pop_first().map(|((t, _), map)| (t, OrdSet { map }))
+    }
+
+    pub fn pop_last(&self) -> Option<(T, OrdSet<T,C>)> {
+        self.map.pop_last().map(|((t, _), map)| (t, OrdSet { map }))
+    }
+
+    pub fn iter(&self) -> SetIter<'_, T> {
+        SetIter(self.map.keys())
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> SetIter<'_, T>
+        where T : Borrow<Q>, Q : ?Sized, R : RangeBounds<Q>, C : Comparator<Q> {
+        SetIter(Keys(self.map.range(range)))
+    }
+
+    pub fn check_invariants(&self) -> Result<(), InvariantError<T>> {
+        self.map.check_invariants()
+    }
+
+    pub fn split<Q>(&self, value: &Q) -> (OrdSet<T,C>, bool, OrdSet<T,C>)
+        where T : Borrow<Q>, Q : ?Sized, C : Comparator<Q> {
+        let (l, found, r) = self.map.split(value);
+        (OrdSet { map: l }, found.is_some(), OrdSet { map: r })
+    }
+}
+
+impl <T,C> OrdSet<T,C> where T : Clone, C : Comparator<T> + Clone + PartialEq {
+    pub fn join(left: &OrdSet<T,C>, value: T, right: &OrdSet<T,C>) -> OrdSet<T,C> {
+        OrdSet { map: OrdMap::join(&left.map, value, (), &right.map) }
+    }
+
+    pub fn union(&self, other: &OrdSet<T,C>) -> OrdSet<T,C> {
+        OrdSet { map: self.map.union(&other.map) }
+    }
+
+    pub fn intersection(&self, other: &OrdSet<T,C>) -> OrdSet<T,C> {
+        OrdSet { map: self.map.intersection(&other.map) }
+    }
+
+    pub fn difference(&self, other: &OrdSet<T,C>) -> OrdSet<T,C> {
+        OrdSet { map: self.map.difference(&other.map) }
+    }
+
+    pub fn symmetric_difference(&self, other: &OrdSet<T,C>) -> OrdSet<T,C> {
+        OrdSet { map: self.map.symmetric_difference(&other.map) }
+    }
+}
+
+impl <T,C> Default for OrdSet<T,C> where T : Clone, C : Comparator<T> + Clone + Default {
+    fn default() -> OrdSet<T,C> {
+        OrdSet { map: OrdMap::default() }
+    }
+}
+
+impl <T,C> FromIterator<T> for OrdSet<T,C> where T : Clone, C : Comparator<T> + Clone + Default {
+    fn from_iter<I>(iter: I) -> Self where I : IntoIterator<Item=T> {
+        OrdSet { map: iter.into_iter().map(|t| (t, ())).collect() }
+    }
+}
+
+impl <'a, T, C> IntoIterator for &'a OrdSet<T,C> where T : Clone, C : Comparator<T> + Clone {
+    type Item = &'a T;
+    type IntoIter = SetIter<'a, T>;
+    fn into_iter(self) -> SetIter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct SetIter<'a, T>(Keys<'a, T, ()>)
+where T : 'a;
+
+impl <'a, T> Iterator for SetIter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next()
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for SetIter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.0.next_back()
+    }
+}
+
+/// Caches the greatest end among the intervals in a subtree.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MaxEnd;
+
+impl <K,V> Measure<Range<K>,V> for MaxEnd where K : Clone + Ord {
+    type Summary = Option<K>;
+
+    fn empty() -> Option<K> {
+        None
+    }
+
+    fn measure(key: &Range<K>, _: &V) -> Option<K> {
+        Some(key.end.clone())
+    }
+
+    fn combine(left: &Option<K>, right: &Option<K>) -> Option<K> {
+        cmp::max(left, right).clone()
+    }
+}
+
+// Orders intervals by start, then by end.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct ByBounds;
+
+impl <K> Comparator<Range<K>> for ByBounds where K : Ord {
+    fn compare(&self, a: &Range<K>, b: &Range<K>) -> Ordering {
+        a.start.cmp(&b.start).then_with(|| a.end.cmp(&b.end))
+    }
+}
+
+/// A map from half-open intervals `[start, end)` to values. Each subtree
+/// caches its greatest end, so overlap queries skip subtrees that end too early.
+#[derive(Clone)]
+pub struct IntervalMap<K, V> where K : Clone + Ord {
+    map: OrdMap<Range<K>, V, ByBounds, MaxEnd>
+}
+
+impl <K,V> IntervalMap<K,V> where K : Clone + Ord, V : Clone {
+    pub fn new() -> IntervalMap<K,V> {
+        IntervalMap { map: OrdMap::measured(ByBounds) }
+    }
+
+    pub fn insert(&self, interval: Range<K>, value: V) -> IntervalMap<K,V> {
+        IntervalMap { map: self.map.insert(interval, value) }
+    }
+
+    pub fn remove(&self, interval: &Range<K>) -> IntervalMap<K,V> {
+        IntervalMap { map: self.map.remove(interval) }
+    }
+
+    pub fn get(&self, interval: &Range<K>) -> Option<&V> {
+        self.map.get(interval)
+    }
+
+    pub fn contains(&self, interval: &Range<K>) -> bool {
+        self.map.contains_key(interval)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterates over the intervals ordered by start, then by end.
+    pub fn iter(&self) -> Iter<'_, Range<K>, V, MaxEnd> {
+        self.map.iter()
+    }
+
+    /// The intervals sharing at least one point with `range`, in order. Empty
+    /// intervals contain no points, so never overlap anything.
+    pub fn overlapping(&self, range: Range<K>) -> Overlapping<'_, K, V> {
+        let empty = range.start >= range.end;
+        let mut iter = Overlapping::new(&self.map.tree, range.start, Bound::Excluded(range.end));
+        if empty {
+            iter.stack.clear();
+        }
+        iter
+    }
+
+    /// The intervals containing `point`, in order.
+    pub fn stabbing(&self, point: K) -> Overlapping<'_, K, V> {
+        Overlapping::new(&self.map.tree, point.clone(), Bound::Included(point))
+    }
+}
+
+impl <K,V> Default for IntervalMap<K,V> where K : Clone + Ord, V : Clone {
+    fn default() -> IntervalMap<K,V> {
+        IntervalMap::new()
+    }
+}
+
+impl <K,V> FromIterator<(Range<K>, V)> for IntervalMap<K,V> where K : Clone + Ord, V : Clone {
+    fn from_iter<I>(iter: I) -> Self where I : IntoIterator<Item=(Range<K>, V)> {
+        IntervalMap { map: iter.into_iter().collect() }
+    }
+}
+
+impl <'a, K, V> IntoIterator for &'a IntervalMap<K,V> where K : Clone + Ord, V : Clone {
+    type Item = (&'a Range<K>, &'a V);
+    type IntoIter = Iter<'a, Range<K>, V, MaxEnd>;
+    fn into_iter(self) -> Iter<'a, Range<K>, V, MaxEnd> {
+        self.iter()
+    }
+}
+
+/// Intervals ending after `after` and starting before `before`, in order.
+pub struct Overlapping<'a, K, V>
+where K : 'a + Clone + Ord, V : 'a {
+    stack: Vec<&'a Node<Range<K>,V,MaxEnd>>,
+    after: K,
+    before: Bound<K>
+}
+
+impl <'a, K, V> Overlapping<'a, K, V> where K : Clone + Ord {
+    fn new(tree: &'a Tree<Range<K>,V,MaxEnd>, after: K, before: Bound<K>) -> Overlapping<'a, K, V> {
+        let mut iter = Overlapping { stack: Vec::new(), after, before };
+        iter.push_left(tree);
+        iter
+    }
+
+    // Descends leftwards, skipping subtrees in which every interval ends too early.
+    fn push_left(&mut self, mut tree: &'a Tree<Range<K>,V,MaxEnd>) {
+        while let Some(ref node) = tree.0 {
+            if node.summary.as_ref().is_none_or(|end| *end <= self.after) {
+                return;
+            }
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+}
+
+impl <'a, K, V> Iterator for Overlapping<'a, K, V> where K : Clone + Ord {
+    type Item = (&'a Range<K>, &'a V);
+    fn next(&mut self) -> Option<(&'a Range<K>, &'a V)> {
+        while let Some(node) = self.stack.pop() {
+            let starts_before = match self.before {
+                Bound::Included(ref k) => node.key.start <= *k,
+                Bound::Excluded(ref k) => node.key.start < *k,
+                Bound::Unbounded => true,
+            };
+            if !starts_before {
+                // Everything left to visit starts later still.
+                self.stack.clear();
+                return None;
+            }
+            self.push_left(&node.right);
+            if node.key.end > self.after && node.key.start < node.key.end {
+                return Some((&node.key, &node.value));
+            }
+        }
+        None
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::Tree;
+    use super::{ Natural, Tree };
 
     #[test] 
     fn construction() {
-        let tree = (0..10).fold(Tree(None), |acc, e| acc.updated(e, (), false));
+        let empty: Tree<i32, ()> = Tree(None);
+        let tree = (0..10).fold(empty, |acc, e| acc.updated(e, (), false, &Natural));
         for i in 0..10 {
-            assert!(tree.contains(i));
-            assert!(!tree.removed(i).contains(i));
+            assert!(tree.contains(&i, &Natural));
+            assert!(!tree.removed(&i, &Natural).unwrap().contains(&i, &Natural));
+        }
+        assert!(!tree.contains(&-1, &Natural));
+        assert!(!tree.contains(&10, &Natural))
+    }
+
+    #[test]
+    fn removing_absent_keys_keeps_invariants() {
+        let empty: Tree<i32, ()> = Tree(None);
+        let tree = (0..20).map(|i| i * 2).fold(empty, |acc, e| acc.updated(e, (), false, &Natural).to_black());
+        for i in 0..40 {
+            match tree.removed(&i, &Natural) {
+                Some(removed) => assert_eq!(removed.to_black().check(None, None, &Natural).err(), None),
+                None => assert!(i % 2 == 1),
+            }
         }
-        assert!(!tree.contains(-1));
-        assert!(!tree.contains(10))
     }
 }
@@ -1,10 +1,15 @@
 use std::clone::Clone;
-use std::iter::{ FromIterator, IntoIterator };
+use std::cmp;
+use std::iter::{ self, FromIterator, IntoIterator };
 use std::ops::Index;
 use std::rc::Rc;
 
+/// A persistent vector: a relaxed radix balanced trie of 32-element blocks,
+/// followed by a tail of up to 32 elements that appends copy instead of the
+/// trie path. Vectors concatenate and split in O(log n).
 pub struct Vector<T> {
-    root: Rc<Tree32<T>>,
+    root: Tree32<T>,
+    tail: Rc<[T]>,
     size: u32,
     shift: u32
 }
@@ -13,28 +18,254 @@ impl <T> Vector<T> {
     pub fn new() -> Vector<T> {
         Vector {
             root: Tree32::new(),
+            tail: Rc::new([]),
             size: 0,
             shift: 0,
         }
     }
 
+    // The index of the first element in the tail.
+    fn tail_offset(&self) -> u32 {
+        self.size - self.tail.len() as u32
+    }
+
+    // The trie with the tail pushed into it as a block, and its shift.
+    fn push_tail(&self) -> (Tree32<T>, u32) {
+        if self.tail.is_empty() {
+            return (self.root.clone(), self.shift);
+        }
+        let leaf = Tree32::Leaf(self.tail.clone());
+        if self.tail_offset() == 0 {
+            return (leaf, 0);
+        }
+        match Tree32::push_leaf(&self.root, self.shift, &leaf) {
+            Some(root) => (root, self.shift),
+            None => {
+                let path = Tree32::path(self.shift, &leaf);
+                (Tree32::branch(vec![self.root.clone(), path]), self.shift + 5)
+            },
+        }
+    }
+
     pub fn append(&self, value: &T) -> Vector<T> where T : Clone {
-        if self.size >> self.shift == 32 {
-            let root = Tree32::deepen(&self.root);
-            let shift = self.shift + 5;
-            let root = root.update(shift, self.size, &Some(value));
-            Vector {
-                root: root,
+        if self.tail.len() < 32 {
+            return Vector {
+                root: self.root.clone(),
+                tail: self.tail.iter().chain(iter::once(value)).cloned().collect(),
                 size: self.size + 1,
-                shift: shift
+                shift: self.shift
+            };
+        }
+        let (root, shift) = self.push_tail();
+        Vector {
+            root,
+            tail: Rc::new([value.clone()]),
+            size: self.size + 1,
+            shift
+        }
+    }
+
+    /// Returns the last element and the vector without it, or `None` if the
+    /// vector is empty.
+    pub fn pop(&self) -> Option<(T, Vector<T>)> where T : Clone {
+        if self.size == 0 {
+            return None;
+        }
+        let last = self[self.size - 1].clone();
+        Some((last, self.truncate(self.size - 1)))
+    }
+
+    /// Returns the vector without its last `n` elements.
+    pub fn drop_last(&self, n: u32) -> Vector<T> where T : Clone {
+        self.truncate(self.size.saturating_sub(n))
+    }
+
+    /// Returns the first `len` elements of the vector.
+    pub fn truncate(&self, len: u32) -> Vector<T> where T : Clone {
+        if len >= self.size {
+            return self.clone();
+        }
+        if len == 0 {
+            return Vector::new();
+        }
+        let offset = self.tail_offset();
+        if len > offset {
+            return Vector {
+                root: self.root.clone(),
+                tail: Rc::from(&self.tail[.. (len - offset) as usize]),
+                size: len,
+                shift: self.shift
+            };
+        }
+        // The block holding the last element kept becomes the tail, and the
+        // trie keeps everything before it.
+        let (chunk, position) = self.root.chunk(self.shift, len - 1);
+        let tail = Rc::from(&chunk[..= position]);
+        let start = len - 1 - position as u32;
+        let (root, shift) = if start == 0 {
+            (Tree32::new(), 0)
+        } else {
+            Tree32::normalize(Tree32::take(&self.root, self.shift, start), self.shift)
+        };
+        Vector {
+            root,
+            tail,
+            size: len,
+            shift
+        }
+    }
+
+    /// Returns the first `index` elements and the rest, or `None` if `index`
+    /// is greater than the length.
+    pub fn split_at(&self, index: u32) -> Option<(Vector<T>, Vector<T>)> where T : Clone {
+        if index > self.size {
+            return None;
+        }
+        let offset = self.tail_offset();
+        let rest = if index >= offset {
+            Vector {
+                root: Tree32::new(),
+                tail: Rc::from(&self.tail[(index - offset) as usize ..]),
+                size: self.size - index,
+                shift: 0
             }
         } else {
-            let root = self.root.update(self.shift, self.size, &Some(value));
+            let (root, shift) = Tree32::normalize(Tree32::skip(&self.root, self.shift, index), self.shift);
             Vector {
-                root: root,
-                size: self.size + 1,
-                shift: self.shift
+                root,
+                tail: self.tail.clone(),
+                size: self.size - index,
+                shift
             }
+        };
+        Some((self.truncate(index), rest))
+    }
+
+    /// Returns this vector followed by `other`.
+    pub fn concat(&self, other: &Vector<T>) -> Vector<T> where T : Clone {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+        if other.tail_offset() == 0 {
+            return self.concat_tail(&other.tail);
+        }
+        let (root, shift) = self.push_tail();
+        let nodes = Tree32::merge(&root, shift, &other.root, other.shift);
+        let shift = cmp::max(shift, other.shift);
+        let (root, shift) = if nodes.len() == 1 {
+            (nodes[0].clone(), shift)
+        } else {
+            (Tree32::branch(nodes), shift + 5)
+        };
+        Vector {
+            root,
+            tail: other.tail.clone(),
+            size: self.size + other.size,
+            shift
+        }
+    }
+
+    // This vector followed by `elements`, which join the tail. If they
+    // overflow it, a full block of them is pushed into the trie first.
+    fn concat_tail(&self, elements: &[T]) -> Vector<T> where T : Clone {
+        let size = self.size + elements.len() as u32;
+        let joined: Vec<T> = self.tail.iter().chain(elements).cloned().collect();
+        if joined.len() <= 32 {
+            return Vector {
+                root: self.root.clone(),
+                tail: Rc::from(joined),
+                size,
+                shift: self.shift
+            };
+        }
+        let full = Vector {
+            root: self.root.clone(),
+            tail: Rc::from(&joined[.. 32]),
+            size: self.tail_offset() + 32,
+            shift: self.shift
+        };
+        let (root, shift) = full.push_tail();
+        Vector {
+            root,
+            tail: Rc::from(&joined[32 ..]),
+            size,
+            shift
+        }
+    }
+
+    /// Returns a vector with `value` inserted before `index`, or `None` if
+    /// `index` is greater than the length.
+    pub fn insert(&self, index: u32, value: &T) -> Option<Vector<T>> where T : Clone {
+        self.split_at(index).map(|(front, back)| front.append(value).concat(&back))
+    }
+
+    /// Returns the element at `index` and the vector without it, or `None`
+    /// if `index` is out of range.
+    pub fn remove(&self, index: u32) -> Option<(T, Vector<T>)> where T : Clone {
+        let value = self.get(index)?.clone();
+        let (front, back) = self.split_at(index)?;
+        let (_, back) = back.split_at(1)?;
+        Some((value, front.concat(&back)))
+    }
+
+    pub fn len(&self) -> u32 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn get(&self, index: u32) -> Option<&T> {
+        let offset = self.tail_offset();
+        if index >= self.size {
+            None
+        } else if index >= offset {
+            self.tail.get((index - offset) as usize)
+        } else {
+            let (chunk, position) = self.root.chunk(self.shift, index);
+            Some(&chunk[position])
+        }
+    }
+
+    /// Returns a vector with the element at `index` replaced by `value`,
+    /// or `None` if `index` is out of range.
+    pub fn set(&self, index: u32, value: &T) -> Option<Vector<T>> where T : Clone {
+        let offset = self.tail_offset();
+        if index >= self.size {
+            return None;
+        }
+        let (root, tail) = if index >= offset {
+            (self.root.clone(), Tree32::replaced(&self.tail, (index - offset) as usize, value))
+        } else {
+            (self.root.update(self.shift, index, value), self.tail.clone())
+        };
+        Some(Vector {
+            root,
+            tail,
+            size: self.size,
+            shift: self.shift
+        })
+    }
+
+    /// Returns a vector with the element at `index` replaced by `f` applied
+    /// to it, or `None` if `index` is out of range.
+    pub fn update<F>(&self, index: u32, f: F) -> Option<Vector<T>>
+        where T : Clone, F : FnOnce(&T) -> T {
+        self.get(index).map(f).and_then(|value| self.set(index, &value))
+    }
+}
+
+impl <T> Clone for Vector<T> {
+    fn clone(&self) -> Vector<T> {
+        Vector {
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+            size: self.size,
+            shift: self.shift
         }
     }
 }
@@ -48,115 +279,265 @@ impl <A> FromIterator<A> for Vector<A> where A : Clone {
 impl <T> Index<u32> for Vector<T> {
     type Output = T;
     fn index<'a>(&'a self, index: u32) -> &'a Self::Output {
-        self.root.lookup(self.shift, index).unwrap()
+        self.get(index).unwrap()
     }
 }
 
+// A trie node. Leaves hold up to 32 elements in their own allocation and sit
+// at shift 0; each level of `Root` above them adds 5 to the shift and holds up
+// to 32 children in its allocation, each beside the cumulative size up to its
+// end. A node whose children are all full but the last is strict, and its
+// child is found by radix alone; in a relaxed one the search continues from
+// there through the sizes. Indices passed to a node are relative to its
+// first element.
 enum Tree32<T> {
-    Root([Option<Rc<Tree32<T>>>; 32]),
-    Leaf(T)
+    Root(Rc<[Child<T>]>),
+    Leaf(Rc<[T]>)
+}
+
+struct Child<T> {
+    end: u32,
+    tree: Tree32<T>
+}
+
+impl <T> Clone for Tree32<T> {
+    fn clone(&self) -> Tree32<T> {
+        match *self {
+            Tree32::Root(ref children) => Tree32::Root(children.clone()),
+            Tree32::Leaf(ref elements) => Tree32::Leaf(elements.clone()),
+        }
+    }
+}
+
+impl <T> Clone for Child<T> {
+    fn clone(&self) -> Child<T> {
+        Child { end: self.end, tree: self.tree.clone() }
+    }
 }
 
 impl <T> Tree32<T> {
-    fn new() -> Rc<Tree32<T>> {
-        Rc::new(Tree32::Root([
-             None, None, None, None, None, None, None, None, None, None, None,
-             None, None, None, None, None, None, None, None, None, None, None,
-             None, None, None, None, None, None, None, None, None, None, ]))
-    }
-
-    fn get_or_create_subtree(children: &[Option<Rc<Tree32<T>>>;32], i: u32) -> Rc<Tree32<T>> {
-        children[i as usize].as_ref().map_or_else(Tree32::new, |c| c.clone())
-    }
-
-    fn replace_subtree(&self, index: u32, subtree: &Rc<Tree32<T>>) -> Rc<Tree32<T>> {
-        if let &Tree32::Root(ref children) = self {
-            let children = [
-                if index == 0 { Some(subtree.clone()) } else { children[0].clone() },
-                if index == 1 { Some(subtree.clone()) } else { children[1].clone() },
-                if index == 2 { Some(subtree.clone()) } else { children[2].clone() },
-                if index == 3 { Some(subtree.clone()) } else { children[3].clone() },
-                if index == 4 { Some(subtree.clone()) } else { children[4].clone() },
-                if index == 5 { Some(subtree.clone()) } else { children[5].clone() },
-                if index == 6 { Some(subtree.clone()) } else { children[6].clone() },
-                if index == 7 { Some(subtree.clone()) } else { children[7].clone() },
-                if index == 8 { Some(subtree.clone()) } else { children[8].clone() },
-                if index == 9 { Some(subtree.clone()) } else { children[9].clone() },
-                if index == 10 { Some(subtree.clone()) } else { children[10].clone() },
-                if index == 11 { Some(subtree.clone()) } else { children[11].clone() },
-                if index == 12 { Some(subtree.clone()) } else { children[12].clone() },
-                if index == 13 { Some(subtree.clone()) } else { children[13].clone() },
-                if index == 14 { Some(subtree.clone()) } else { children[14].clone() },
-                if index == 15 { Some(subtree.clone()) } else { children[15].clone() },
-                if index == 16 { Some(subtree.clone()) } else { children[16].clone() },
-                if index == 17 { Some(subtree.clone()) } else { children[17].clone() },
-                if index == 18 { Some(subtree.clone()) } else { children[18].clone() },
-                if index == 19 { Some(subtree.clone()) } else { children[19].clone() },
-                if index == 20 { Some(subtree.clone()) } else { children[20].clone() },
-                if index == 21 { Some(subtree.clone()) } else { children[21].clone() },
-                if index == 22 { Some(subtree.clone()) } else { children[22].clone() },
-                if index == 23 { Some(subtree.clone()) } else { children[23].clone() },
-                if index == 24 { Some(subtree.clone()) } else { children[24].clone() },
-                if index == 25 { Some(subtree.clone()) } else { children[25].clone() },
-                if index == 26 { Some(subtree.clone()) } else { children[26].clone() },
-                if index == 27 { Some(subtree.clone()) } else { children[27].clone() },
-                if index == 28 { Some(subtree.clone()) } else { children[28].clone() },
-                if index == 29 { Some(subtree.clone()) } else { children[29].clone() },
-                if index == 30 { Some(subtree.clone()) } else { children[30].clone() },
-                if index == 31 { Some(subtree.clone()) } else { children[31].clone() },
-            ];
-            Rc::new(Tree32::Root(children))
+    fn new() -> Tree32<T> {
+        Tree32::Root(Rc::new([]))
+    }
+
+    // A node over `children`, recording where each ends.
+    fn branch<I>(children: I) -> Tree32<T> where I : IntoIterator<Item=Tree32<T>> {
+        let mut end = 0;
+        Tree32::Root(children.into_iter().map(|tree| {
+            end += tree.len();
+            Child { end, tree }
+        }).collect())
+    }
+
+    // A chain of single-child nodes from `shift` down to `leaf`.
+    fn path(shift: u32, leaf: &Tree32<T>) -> Tree32<T> {
+        if shift == 0 {
+            leaf.clone()
         } else {
-            panic!("Tried to replace child of Leaf")
+            Tree32::branch(iter::once(Tree32::path(shift - 5, leaf)))
         }
     }
 
-    fn deepen(root: &Rc<Tree32<T>>) -> Rc<Tree32<T>> {
-        Rc::new(Tree32::Root([
-             Some(root.clone()), None, None, None, None, None, None, None, None, None, None,
-             None, None, None, None, None, None, None, None, None, None, None,
-             None, None, None, None, None, None, None, None, None, None, ]))
+    // Drops single-child roots.
+    fn normalize(mut root: Tree32<T>, mut shift: u32) -> (Tree32<T>, u32) {
+        loop {
+            let child = match root {
+                Tree32::Root(ref children) if shift > 0 && children.len() == 1 => children[0].tree.clone(),
+                _ => return (root, shift),
+            };
+            root = child;
+            shift -= 5;
+        }
     }
 
-    fn lookup<'a> (&'a self, shift: u32, index: u32) -> Option<&'a T> {
-        if let &Tree32::Root(ref children) = self {
-            let idx = (index >> shift) & 0b11111;
-            if shift > 0 {
-                match children[idx as usize].as_ref() {
-                    Some(ref child) => child.lookup(shift - 5, index),
-                    None => None
-                }
-            } else {
-                match children[idx as usize] {
-                    Some(ref child) =>
-                        if let Tree32::Leaf(ref t) = **child {
-                            Some(t)
-                        } else {
-                            panic!("Lookup ended at non-leaf node");
-                        },
-                    None => None
+    fn children(&self) -> &[Child<T>] {
+        match *self {
+            Tree32::Root(ref children) => children,
+            Tree32::Leaf(_) => panic!("Tried to take the children of a Leaf"),
+        }
+    }
+
+    fn elements(&self) -> &[T] {
+        match *self {
+            Tree32::Root(_) => panic!("Tried to take the elements of a Root"),
+            Tree32::Leaf(ref elements) => elements,
+        }
+    }
+
+    // The number of children or elements held directly.
+    fn slots(&self) -> usize {
+        match *self {
+            Tree32::Root(ref children) => children.len(),
+            Tree32::Leaf(ref elements) => elements.len(),
+        }
+    }
+
+    fn len(&self) -> u32 {
+        match *self {
+            Tree32::Root(ref children) => children.last().map_or(0, |c| c.end),
+            Tree32::Leaf(ref elements) => elements.len() as u32,
+        }
+    }
+
+    // The child holding `index` and the index within that child.
+    fn child_at(&self, shift: u32, index: u32) -> (usize, u32) {
+        let children = self.children();
+        let mut i = (index >> shift) as usize;
+        while children[i].end <= index {
+            i += 1;
+        }
+        (i, if i == 0 { index } else { index - children[i - 1].end })
+    }
+
+    // The leaf holding `index` and the position of `index` in it.
+    fn chunk(&self, shift: u32, index: u32) -> (&[T], usize) {
+        match *self {
+            Tree32::Root(ref children) => {
+                let (i, index) = self.child_at(shift, index);
+                children[i].tree.chunk(shift - 5, index)
+            },
+            Tree32::Leaf(ref elements) => (elements, index as usize),
+        }
+    }
+
+    // A copy of `elements` with the one at `index` replaced by `value`.
+    fn replaced(elements: &[T], index: usize, value: &T) -> Rc<[T]> where T : Clone {
+        elements.iter().enumerate().map(|(i, e)| if i == index { value } else { e }).cloned().collect()
+    }
+
+    fn update(&self, shift: u32, index: u32, value: &T) -> Tree32<T> where T : Clone {
+        match *self {
+            Tree32::Root(ref children) => {
+                let (i, index) = self.child_at(shift, index);
+                Tree32::Root(children.iter().enumerate().map(|(j, c)| if j == i {
+                    Child { end: c.end, tree: c.tree.update(shift - 5, index, value) }
+                } else {
+                    c.clone()
+                }).collect())
+            },
+            Tree32::Leaf(ref elements) => Tree32::Leaf(Tree32::replaced(elements, index as usize, value)),
+        }
+    }
+
+    // The subtrees held by the node's children.
+    fn subtrees(&self) -> impl Iterator<Item=Tree32<T>> + '_ {
+        self.children().iter().map(|c| c.tree.clone())
+    }
+
+    // Adds `leaf` after the last leaf under `node`, or returns `None` if
+    // every node on the right edge is full.
+    fn push_leaf(node: &Tree32<T>, shift: u32, leaf: &Tree32<T>) -> Option<Tree32<T>> {
+        let children = match *node {
+            Tree32::Root(ref children) => children,
+            Tree32::Leaf(_) => return None,
+        };
+        if shift > 5 {
+            if let Some((last, init)) = children.split_last() {
+                if let Some(last) = Tree32::push_leaf(&last.tree, shift - 5, leaf) {
+                    return Some(Tree32::branch(init.iter().map(|c| c.tree.clone()).chain(iter::once(last))));
                 }
             }
+        }
+        if children.len() < 32 {
+            Some(Tree32::branch(node.subtrees().chain(iter::once(Tree32::path(shift - 5, leaf)))))
         } else {
-            panic!("Tried to do direct lookup on Leaf");
+            None
+        }
+    }
+
+    // The first `n` elements under `node`, where `n` is positive.
+    fn take(node: &Tree32<T>, shift: u32, n: u32) -> Tree32<T> where T : Clone {
+        if n == node.len() {
+            return node.clone();
+        }
+        match *node {
+            Tree32::Root(ref children) => {
+                let (i, index) = node.child_at(shift, n - 1);
+                let last = Tree32::take(&children[i].tree, shift - 5, index + 1);
+                Tree32::branch(node.subtrees().take(i).chain(iter::once(last)))
+            },
+            Tree32::Leaf(ref elements) => Tree32::Leaf(Rc::from(&elements[.. n as usize])),
+        }
+    }
+
+    // The elements under `node` after the first `n`, of which there must be some.
+    fn skip(node: &Tree32<T>, shift: u32, n: u32) -> Tree32<T> where T : Clone {
+        if n == 0 {
+            return node.clone();
+        }
+        match *node {
+            Tree32::Root(ref children) => {
+                let (i, index) = node.child_at(shift, n);
+                let first = Tree32::skip(&children[i].tree, shift - 5, index);
+                Tree32::branch(iter::once(first).chain(node.subtrees().skip(i + 1)))
+            },
+            Tree32::Leaf(ref elements) => Tree32::Leaf(Rc::from(&elements[n as usize ..])),
         }
     }
 
-    fn update(&self, shift: u32, index: u32, value: &Option<&T>) -> Rc<Tree32<T>>
+    // Concatenates two subtrees into one or two nodes at the greater of
+    // their shifts, merging only along the edges where they meet.
+    fn merge(left: &Tree32<T>, lshift: u32, right: &Tree32<T>, rshift: u32) -> Vec<Tree32<T>>
         where T : Clone {
-        if let &Tree32::Root(ref children) = self {
-            let idx = (index >> shift) & 0b11111;
-            if shift > 0 {
-                let subtree = Tree32::get_or_create_subtree(children, idx);
-                let subtree = subtree.update(shift - 5, index, value);
-                self.replace_subtree(idx, &subtree)
-            } else {
-                let subtree = value.map_or_else(Tree32::new, |v| Rc::new(Tree32::Leaf(v.clone())));
-                self.replace_subtree(idx, &subtree)
-            }
+        let shift = cmp::max(lshift, rshift);
+        if shift == 0 {
+            return Tree32::rebalance(vec![left.clone(), right.clone()], 0);
+        }
+        let mut nodes = Vec::with_capacity(64);
+        if lshift > rshift {
+            let (last, init) = left.children().split_last().unwrap();
+            nodes.extend(init.iter().map(|c| c.tree.clone()));
+            nodes.extend(Tree32::merge(&last.tree, lshift - 5, right, rshift));
+        } else if lshift < rshift {
+            let (first, rest) = right.children().split_first().unwrap();
+            nodes.extend(Tree32::merge(left, lshift, &first.tree, rshift - 5));
+            nodes.extend(rest.iter().map(|c| c.tree.clone()));
+        } else {
+            let (last, init) = left.children().split_last().unwrap();
+            let (first, rest) = right.children().split_first().unwrap();
+            nodes.extend(init.iter().map(|c| c.tree.clone()));
+            nodes.extend(Tree32::merge(&last.tree, shift - 5, &first.tree, shift - 5));
+            nodes.extend(rest.iter().map(|c| c.tree.clone()));
+        }
+        Tree32::rebalance(nodes, shift - 5).chunks(32).map(|c| Tree32::branch(c.iter().cloned())).collect()
+    }
+
+    // Repacks sibling nodes at `shift` into as few as possible when they
+    // number more than two beyond that, bounding how relaxed a node can get.
+    fn rebalance(nodes: Vec<Tree32<T>>, shift: u32) -> Vec<Tree32<T>> where T : Clone {
+        let slots: usize = nodes.iter().map(|n| n.slots()).sum();
+        if nodes.len() <= slots.div_ceil(32) + 2 {
+            return nodes;
+        }
+        if shift == 0 {
+            let elements: Vec<T> = nodes.iter().flat_map(|n| n.elements().iter().cloned()).collect();
+            elements.chunks(32).map(|c| Tree32::Leaf(Rc::from(c))).collect()
         } else {
-            panic!("Requested direct update to Leaf!")
+            let children: Vec<Tree32<T>> = nodes.iter().flat_map(|n| n.subtrees()).collect();
+            children.chunks(32).map(|c| Tree32::branch(c.iter().cloned())).collect()
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{ Tree32, Vector };
+
+    fn leaves<T>(tree: &Tree32<T>) -> usize {
+        match *tree {
+            Tree32::Root(ref children) => children.iter().map(|c| leaves(&c.tree)).sum(),
+            Tree32::Leaf(_) => 1,
+        }
+    }
+
+    #[test]
+    fn concatenation_keeps_leaves_full() {
+        let single = (0..100000).fold(Vector::new(), |v, i| v.concat(&Vector::new().append(&i)));
+        assert_eq!(single.shift, 15);
+        assert_eq!(leaves(&single.root), 3124);
+
+        let pieces = (0..3000).fold(Vector::new(), |v, i| v.concat(&(i .. i + 50).collect()));
+        assert_eq!(pieces.shift, 15);
+        assert!(leaves(&pieces.root) * 28 <= pieces.len() as usize);
+    }
+}
@@ -0,0 +1,40 @@
+extern crate dogged;
+use dogged::bag::*;
+
+#[test]
+fn counts_and_repetition() {
+    let bag: Bag<char> = "mississippi".chars().collect();
+    assert_eq!(bag.len(), 11);
+    assert_eq!(bag.distinct_len(), 4);
+    assert_eq!(bag.count(&'s'), 4);
+    assert_eq!(bag.count(&'x'), 0);
+    assert_eq!(bag.iter().collect::<String>(), "iiiimppssss");
+
+    let fewer = bag.remove(&'s').remove_n(&'p', 5).insert_n('m', 2);
+    assert_eq!(fewer.iter().collect::<String>(), "iiiimmmsss");
+    assert_eq!(fewer.len(), 10);
+    assert!(!fewer.contains(&'p'));
+    assert_eq!(bag.len(), 11);
+    assert_eq!(bag.insert_n('z', 0).len(), 11);
+    assert_eq!(bag.remove_n(&'s', 0).len(), 11);
+    assert_eq!(bag.remove(&'x').len(), 11);
+}
+
+#[test]
+fn remove_borrowed() {
+    let bag: Bag<String> = vec!["a", "b", "a"].into_iter().map(String::from).collect();
+    assert_eq!(bag.remove("a").count("a"), 1);
+    assert_eq!(bag.remove_n("a", 2).distinct_len(), 1);
+    assert_eq!(bag.remove("z").len(), 3);
+}
+
+#[test]
+fn multiset_algebra() {
+    let a: Bag<u32> = vec![1, 1, 2, 3].into_iter().collect();
+    let b: Bag<u32> = vec![1, 2, 2, 4].into_iter().collect();
+    assert_eq!(a.sum(&b).iter().cloned().collect::<Vec<_>>(), vec![1, 1, 1, 2, 2, 2, 3, 4]);
+    assert_eq!(a.union(&b).iter().cloned().collect::<Vec<_>>(), vec![1, 1, 2, 2, 3, 4]);
+    assert_eq!(a.intersection(&b).iter().cloned().collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(a.sum(&b).len(), 8);
+    assert_eq!(a.intersection(&b).len(), 2);
+}
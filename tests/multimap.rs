@@ -0,0 +1,28 @@
+extern crate dogged;
+use dogged::multimap::*;
+
+#[test]
+fn insert_and_get_all() {
+    let map: MultiMap<&str, u32> = vec![("a", 1), ("b", 2), ("a", 3)].into_iter().collect();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.get_all("a").cloned().collect::<Vec<_>>(), vec![3, 1]);
+    assert_eq!(map.get_all("b").cloned().collect::<Vec<_>>(), vec![2]);
+    assert_eq!(map.get_all("c").count(), 0);
+    assert_eq!(map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), vec![("a", 3), ("a", 1), ("b", 2)]);
+}
+
+#[test]
+fn remove_one_and_all() {
+    let map = MultiMap::new().insert(1, 'x').insert(1, 'y').insert(1, 'x').insert(2, 'z');
+    let fewer = map.remove_one(&1, &'x');
+    assert_eq!(fewer.len(), 3);
+    assert_eq!(fewer.get_all(&1).cloned().collect::<String>(), "yx");
+    assert_eq!(fewer.remove_one(&1, &'q').len(), 3);
+    assert!(!fewer.remove_one(&2, &'z').contains_key(&2));
+
+    let cleared = map.remove_all(&1);
+    assert_eq!(cleared.len(), 1);
+    assert_eq!(cleared.get_all(&1).count(), 0);
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.get_all(&1).cloned().collect::<String>(), "xyx");
+}
@@ -0,0 +1,748 @@
+extern crate dogged;
+use dogged::redblack::*;
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::atomic::{ AtomicUsize, Ordering as AtomicOrdering };
+
+#[test]
+fn insert_and_get() {
+    let map = (0..100).fold(OrdMap::new(), |m, i| m.insert(i, i * 2));
+    assert_eq!(map.len(), 100);
+    for i in 0..100 {
+        assert_eq!(map.get(&i), Some(&(i * 2)));
+    }
+    assert_eq!(map.get(&100), None);
+}
+
+#[test]
+fn insert_leaves_original_intact() {
+    let before = OrdMap::new().insert("a", 1).insert("b", 2);
+    let after = before.insert("a", 10).insert("c", 3);
+    assert_eq!(before.len(), 2);
+    assert_eq!(before.get(&"a"), Some(&1));
+    assert!(!before.contains_key(&"c"));
+    assert_eq!(after.len(), 3);
+    assert_eq!(after.get(&"a"), Some(&10));
+}
+
+#[test]
+fn remove() {
+    let map = (0..50).fold(OrdMap::new(), |m, i| m.insert(i, i + 100));
+    let evens = (0..50).filter(|i| i % 2 == 1).fold(map.clone(), |m, i| m.remove(&i));
+    assert_eq!(map.len(), 50);
+    assert_eq!(evens.len(), 25);
+    for i in 0..50 {
+        assert_eq!(map.get(&i), Some(&(i + 100)));
+        if i % 2 == 0 {
+            assert_eq!(evens.get(&i), Some(&(i + 100)));
+        } else {
+            assert!(!evens.contains_key(&i));
+        }
+    }
+    assert_eq!(evens.remove(&1).len(), 25);
+    assert_eq!(evens.remove(&1000).len(), 25);
+}
+
+#[test]
+fn borrowed_key_lookup() {
+    let map = OrdMap::new()
+        .insert("apple".to_string(), vec![1, 2])
+        .insert("pear".to_string(), vec![3]);
+    assert_eq!(map.get("apple"), Some(&vec![1, 2]));
+    assert!(map.contains_key("pear"));
+    assert!(!map.contains_key("plum"));
+    assert!(!map.remove("apple").contains_key("apple"));
+
+    let set = OrdSet::new().insert("x".to_string());
+    assert!(set.contains("x"));
+    assert!(set.remove("x").is_empty());
+}
+
+#[test]
+fn empty() {
+    let map: OrdMap<u32, u32> = OrdMap::new();
+    assert!(map.is_empty());
+    assert!(!map.insert(1, 1).is_empty());
+    assert!(map.insert(1, 1).remove(&1).is_empty());
+}
+
+#[test]
+fn set_iterates_in_order() {
+    let set = [5, 3, 9, 1, 7, 3, 5].iter().fold(OrdSet::new(), |s, &i| s.insert(i));
+    assert_eq!(set.len(), 5);
+    assert_eq!(set.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 5, 7, 9]);
+    assert_eq!(set.first(), Some(&1));
+    assert_eq!(set.last(), Some(&9));
+}
+
+#[test]
+fn set_snapshots() {
+    let snapshot = (0..20).fold(OrdSet::new(), |s, i| s.insert(i));
+    let trimmed = snapshot.remove(&0).remove(&19).remove(&42);
+    assert_eq!(snapshot.len(), 20);
+    assert!(snapshot.contains(&0));
+    assert_eq!(trimmed.len(), 18);
+    assert!(!trimmed.contains(&0));
+    assert_eq!(trimmed.first(), Some(&1));
+    assert_eq!(trimmed.last(), Some(&18));
+    assert_eq!((&trimmed).into_iter().count(), 18);
+}
+
+#[test]
+fn empty_set() {
+    let set: OrdSet<u32> = OrdSet::new();
+    assert!(set.is_empty());
+    assert_eq!(set.first(), None);
+    assert_eq!(set.last(), None);
+    assert_eq!(set.iter().next(), None);
+}
+
+#[test]
+fn map_iterates_in_key_order() {
+    let map = [(3, "c"), (1, "a"), (4, "d"), (2, "b")].iter()
+        .fold(OrdMap::new(), |m, &(k, v)| m.insert(k, v));
+    assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&1, &"a"), (&2, &"b"), (&3, &"c"), (&4, &"d")]);
+    assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    assert_eq!(map.values().rev().cloned().collect::<Vec<_>>(), vec!["d", "c", "b", "a"]);
+    let mut n = 0;
+    for (k, _) in &map {
+        n += *k;
+    }
+    assert_eq!(n, 10);
+}
+
+#[test]
+fn iterate_from_both_ends() {
+    let map = (0..100).fold(OrdMap::new(), |m, i| m.insert(i, ()));
+    let mut keys = map.keys();
+    let mut seen = Vec::new();
+    loop {
+        match (keys.next(), keys.next_back()) {
+            (Some(&a), Some(&b)) => { seen.push(a); seen.push(b); },
+            (Some(&a), None) | (None, Some(&a)) => seen.push(a),
+            (None, None) => break,
+        }
+    }
+    seen.sort();
+    assert_eq!(seen, (0..100).collect::<Vec<_>>());
+    assert_eq!(keys.next(), None);
+    assert_eq!(keys.next_back(), None);
+}
+
+#[test]
+fn range_bounds() {
+    let map = (0..100).map(|i| i * 10).fold(OrdMap::new(), |m, t| m.insert(t, t / 10));
+    let keys = |r: Vec<(&u32, &u32)>| r.into_iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    assert_eq!(keys(map.range(15..45).collect()), vec![20, 30, 40]);
+    assert_eq!(keys(map.range(20..=40).collect()), vec![20, 30, 40]);
+    assert_eq!(keys(map.range(..25).collect()), vec![0, 10, 20]);
+    assert_eq!(keys(map.range(975..).collect()), vec![980, 990]);
+    assert_eq!(keys(map.range(20..40).rev().collect()), vec![30, 20]);
+    assert_eq!(map.range(..).count(), 100);
+    assert_eq!(map.range(41..49).count(), 0);
+    assert_eq!(map.range(1000..).count(), 0);
+    assert_eq!(map.range((Bound::Included(50), Bound::Excluded(20))).count(), 0);
+}
+
+#[test]
+fn range_on_old_snapshot() {
+    let old = (0..10).fold(OrdMap::new(), |m, i| m.insert(i, ()));
+    let new = old.remove(&5).insert(20, ());
+    assert_eq!(old.range(4..7).map(|(k, _)| *k).collect::<Vec<_>>(), vec![4, 5, 6]);
+    assert_eq!(new.range(4..).map(|(k, _)| *k).collect::<Vec<_>>(), vec![4, 6, 7, 8, 9, 20]);
+
+    let set = (0..10).fold(OrdSet::new(), |s, i| s.insert(i));
+    assert_eq!(set.range(3..6).cloned().collect::<Vec<_>>(), vec![3, 4, 5]);
+}
+
+#[test]
+fn rank_and_select() {
+    let scores = (0..200).map(|i| i * 3).fold(OrdMap::new(), |m, s| m.insert(s, s + 1));
+    for i in 0..200 {
+        assert_eq!(scores.select(i as usize), Some((&(i * 3), &(i * 3 + 1))));
+        assert_eq!(scores.rank(&(i * 3)), i as usize);
+        assert_eq!(scores.rank(&(i * 3 + 1)), i as usize + 1);
+    }
+    assert_eq!(scores.select(200), None);
+    assert_eq!(scores.rank(&-1), 0);
+
+    let fewer = (0..100).fold(scores.clone(), |m, i| m.remove(&(i * 6)));
+    assert_eq!(fewer.len(), 100);
+    assert_eq!(fewer.select(0), Some((&3, &4)));
+    assert_eq!(fewer.rank(&9), 1);
+    assert_eq!(scores.len(), 200);
+}
+
+#[test]
+fn set_rank_and_select() {
+    let set = (0..10).rev().fold(OrdSet::new(), |s, i| s.insert(i * 2));
+    assert_eq!(set.select(3), Some(&6));
+    assert_eq!(set.rank(&7), 4);
+    assert_eq!(set.insert(6).len(), 10);
+}
+
+#[test]
+fn set_algebra() {
+    let threes = (0..100).filter(|i| i % 3 == 0).fold(OrdSet::new(), |s, i| s.insert(i));
+    let fives = (0..100).filter(|i| i % 5 == 0).fold(OrdSet::new(), |s, i| s.insert(i));
+    let elements = |s: OrdSet<u32>| s.iter().cloned().collect::<Vec<_>>();
+    assert_eq!(elements(threes.union(&fives)),
+               (0..100).filter(|i| i % 3 == 0 || i % 5 == 0).collect::<Vec<_>>());
+    assert_eq!(elements(threes.intersection(&fives)), vec![0, 15, 30, 45, 60, 75, 90]);
+    assert_eq!(elements(threes.difference(&fives)),
+               (0..100).filter(|i| i % 3 == 0 && i % 5 != 0).collect::<Vec<_>>());
+    assert_eq!(elements(threes.symmetric_difference(&fives)),
+               (0..100).filter(|i| (i % 3 == 0) != (i % 5 == 0)).collect::<Vec<_>>());
+    assert_eq!(threes.union(&fives).len(), 47);
+    assert!(threes.difference(&threes).is_empty());
+    assert_eq!(threes.intersection(&OrdSet::new()).len(), 0);
+}
+
+#[test]
+fn map_union_resolves_conflicts() {
+    let a = (0..50).fold(OrdMap::new(), |m, i| m.insert(i, 1));
+    let b = (25..75).fold(OrdMap::new(), |m, i| m.insert(i, 10));
+    let sum = a.union_with(&b, |_, x, y| x + y);
+    assert_eq!(sum.len(), 75);
+    assert_eq!(sum.get(&0), Some(&1));
+    assert_eq!(sum.get(&30), Some(&11));
+    assert_eq!(sum.get(&60), Some(&10));
+    assert_eq!(a.union(&b).get(&30), Some(&1));
+    assert_eq!(b.intersection(&a).values().cloned().collect::<Vec<_>>(), vec![10; 25]);
+}
+
+#[test]
+fn split_and_join() {
+    let map = (0..100).fold(OrdMap::new(), |m, i| m.insert(i, i * i));
+    let (lo, mid, hi) = map.split(&40);
+    assert_eq!(mid, Some(1600));
+    assert_eq!(lo.keys().cloned().collect::<Vec<_>>(), (0..40).collect::<Vec<_>>());
+    assert_eq!(hi.keys().cloned().collect::<Vec<_>>(), (41..100).collect::<Vec<_>>());
+    assert_eq!(map.len(), 100);
+
+    let (_, missing, _) = lo.split(&40);
+    assert_eq!(missing, None);
+
+    let joined = OrdMap::join(&lo, 40, 0, &hi);
+    assert_eq!(joined.len(), 100);
+    assert_eq!(joined.get(&40), Some(&0));
+    assert_eq!(joined.select(41), Some((&41, &1681)));
+
+    let lopsided = OrdMap::join(&OrdMap::new().insert(-1, 1), 0, 0, &hi);
+    assert_eq!(lopsided.keys().cloned().collect::<Vec<_>>(),
+               vec![-1, 0].into_iter().chain(41..100).collect::<Vec<_>>());
+}
+
+#[test]
+#[should_panic]
+fn join_out_of_order() {
+    let map = (0..10).fold(OrdMap::new(), |m, i| m.insert(i, ()));
+    OrdMap::join(&map, 5, (), &OrdMap::new());
+}
+
+#[test]
+fn set_split_and_join() {
+    let set = (0..20).fold(OrdSet::new(), |s, i| s.insert(i));
+    let (lo, found, hi) = set.split(&7);
+    assert!(found);
+    assert_eq!(lo.len() + hi.len(), 19);
+    assert_eq!(OrdSet::join(&lo, 7, &hi).iter().cloned().collect::<Vec<_>>(), (0..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn update_with_counts() {
+    let words = ["a", "b", "a", "c", "b", "a"];
+    let counts = words.iter().fold(OrdMap::new(), |m, w| m.update_with(*w, |n| Some(n.map_or(1, |n| n + 1))));
+    assert_eq!(counts.iter().collect::<Vec<_>>(), vec![(&"a", &3), (&"b", &2), (&"c", &1)]);
+
+    let decremented = counts.update_with("c", |n| n.and_then(|&n| if n > 1 { Some(n - 1) } else { None }));
+    assert_eq!(decremented.len(), 2);
+    assert!(!decremented.contains_key("c"));
+    assert_eq!(counts.get("c"), Some(&1));
+}
+
+#[test]
+fn update_with_removes_and_inserts() {
+    let map = (0..64).fold(OrdMap::new(), |m, i| m.insert(i, i));
+    let odd = (0..64).fold(map.clone(), |m, i| m.update_with(i, |v| v.cloned().filter(|v| v % 2 == 1)));
+    assert_eq!(odd.keys().cloned().collect::<Vec<_>>(), (0..64).filter(|i| i % 2 == 1).collect::<Vec<_>>());
+    let unchanged = odd.update_with(2, |_| None);
+    assert_eq!(unchanged.len(), 32);
+    assert_eq!(odd.update_with(100, |v| v.map(|v| v + 1)).len(), 32);
+    assert_eq!(map.update_with(3, |v| v.map(|v| v * 10)).get(&3), Some(&30));
+}
+
+// Equal whenever the numbers match, whatever the label.
+#[derive(Clone, Debug)]
+struct Labelled(u32, &'static str);
+
+impl PartialEq for Labelled {
+    fn eq(&self, other: &Labelled) -> bool {
+        self.0 == other.0
+    }
+}
+
+#[test]
+fn update_with_eq_keeps_equal_values() {
+    let map: OrdMap<u32, Labelled> = (0..64).map(|k| (k, Labelled(k, "old"))).collect();
+    assert_eq!(map.update_with_eq(7, |v| v.map(|v| Labelled(v.0, "new"))).get(&7).unwrap().1, "old");
+    assert_eq!(map.update_with(7, |v| v.map(|v| Labelled(v.0, "new"))).get(&7).unwrap().1, "new");
+    assert_eq!(map.update_with_eq(7, |v| v.map(|v| Labelled(v.0 + 1, "new"))).get(&7), Some(&Labelled(8, "new")));
+    assert_eq!(map.update_with_eq(100, |_| Some(Labelled(0, "new"))).len(), 65);
+}
+
+#[test]
+fn work_queue() {
+    let queue = [(3, "c"), (1, "a"), (2, "b")].iter().fold(OrdMap::new(), |m, &(k, v)| m.insert(k, v));
+    assert_eq!(queue.first(), Some((&1, &"a")));
+    assert_eq!(queue.last(), Some((&3, &"c")));
+
+    let ((k, v), rest) = queue.pop_first().unwrap();
+    assert_eq!((k, v), (1, "a"));
+    assert_eq!(rest.len(), 2);
+    let ((k, _), rest) = rest.pop_last().unwrap();
+    assert_eq!(k, 3);
+    assert_eq!(rest.keys().cloned().collect::<Vec<_>>(), vec![2]);
+    assert!(rest.pop_first().unwrap().1.pop_first().is_none());
+    assert_eq!(queue.len(), 3);
+}
+
+#[test]
+fn drain_set_in_order() {
+    let mut set = [9, 4, 7, 1].iter().fold(OrdSet::new(), |s, &i| s.insert(i));
+    let mut drained = Vec::new();
+    while let Some((t, rest)) = set.pop_first() {
+        drained.push(t);
+        set = rest;
+    }
+    assert_eq!(drained, vec![1, 4, 7, 9]);
+    assert!(OrdSet::<u32>::new().pop_last().is_none());
+}
+
+#[test]
+fn from_sorted_iter() {
+    for &n in &[0, 1, 2, 3, 7, 8, 100, 1000] {
+        let map = OrdMap::from_sorted_iter((0..n).map(|i| (i, i * 2)));
+        assert_eq!(map.len(), n as usize);
+        assert_eq!(map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+                   (0..n).map(|i| (i, i * 2)).collect::<Vec<_>>());
+        let grown = map.insert(n, 0).remove(&0);
+        assert_eq!(grown.len(), n as usize);
+    }
+    let set = OrdSet::from_sorted_iter(vec!["a", "b", "c"]);
+    assert!(set.contains("b"));
+}
+
+#[test]
+#[should_panic]
+fn from_sorted_iter_rejects_unsorted() {
+    OrdMap::from_sorted_iter(vec![(2, ()), (1, ())]);
+}
+
+#[test]
+fn collect_unsorted() {
+    let map: OrdMap<u32, &str> = vec![(3, "c"), (1, "a"), (3, "z"), (2, "b")].into_iter().collect();
+    assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&1, &"a"), (&2, &"b"), (&3, &"z")]);
+    let set: OrdSet<u32> = vec![5, 1, 5, 3].into_iter().collect();
+    assert_eq!(set.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 5]);
+}
+
+// A deterministic generator of numbers below its argument, for the randomized
+// tests.
+fn random_below(mut seed: u64) -> impl FnMut(u64) -> u64 {
+    move |n| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % n
+    }
+}
+
+#[test]
+fn invariants_hold_under_random_operations() {
+    let mut random = random_below(0x2545F4914F6CDD1D);
+    let mut map = OrdMap::new();
+    let mut other = OrdMap::new();
+    for _ in 0..2000 {
+        let k = random(500);
+        map = match random(4) {
+            0 => map.remove(&k),
+            1 => map.update_with(k, |v| if v.is_some() { None } else { Some(k) }),
+            _ => map.insert(k, k),
+        };
+        other = other.insert(random(500), 0);
+        assert_eq!(map.check_invariants(), Ok(()));
+    }
+    assert_eq!(map.union(&other).check_invariants(), Ok(()));
+    assert_eq!(map.intersection(&other).check_invariants(), Ok(()));
+    assert_eq!(map.difference(&other).check_invariants(), Ok(()));
+    assert_eq!(map.symmetric_difference(&other).check_invariants(), Ok(()));
+    let (lo, _, hi) = map.split(&250);
+    assert_eq!(lo.check_invariants(), Ok(()));
+    assert_eq!(hi.check_invariants(), Ok(()));
+    assert_eq!(OrdMap::from_sorted_iter((0..777).map(|i| (i, ()))).check_invariants(), Ok(()));
+}
+
+#[test]
+fn removal_keeps_values_with_their_keys() {
+    let mut random = random_below(0x5851F42D4C957F2D);
+    let mut map: OrdMap<u64, u64> = (0..300).map(|k| (k, k * 10)).collect();
+    for _ in 0..250 {
+        let k = random(300);
+        map = if random(2) == 0 { map.remove(&k) } else { map.update_with(k, |_| None) };
+        assert_eq!(map.iter().find(|&(k, v)| *v != k * 10), None);
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct CaseInsensitive;
+
+impl Comparator<str> for CaseInsensitive {
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        a.to_lowercase().cmp(&b.to_lowercase())
+    }
+}
+
+impl Comparator<String> for CaseInsensitive {
+    fn compare(&self, a: &String, b: &String) -> Ordering {
+        Comparator::<str>::compare(self, a, b)
+    }
+}
+
+#[test]
+fn custom_comparator() {
+    let map = OrdMap::with_comparator(CaseInsensitive)
+        .insert("Banana".to_string(), 2)
+        .insert("apple".to_string(), 1)
+        .insert("APPLE".to_string(), 3);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get("Apple"), Some(&3));
+    assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec!["APPLE", "Banana"]);
+    assert_eq!(map.get_key_value("banana"), Some((&"Banana".to_string(), &2)));
+    assert_eq!(map.get_key_value("cherry"), None);
+
+    let set: OrdSet<u32, Reverse> = vec![1, 5, 3].into_iter().collect();
+    assert_eq!(set.iter().cloned().collect::<Vec<_>>(), vec![5, 3, 1]);
+    assert_eq!(set.range((Bound::Included(4), Bound::Included(1))).cloned().collect::<Vec<_>>(), vec![3, 1]);
+    assert_eq!(set.union(&OrdSet::with_comparator(Reverse).insert(4)).first(), Some(&5));
+}
+
+#[derive(Clone, PartialEq)]
+struct Collation {
+    order: Vec<char>,
+}
+
+impl Comparator<char> for Collation {
+    fn compare(&self, a: &char, b: &char) -> Ordering {
+        let position = |c| self.order.iter().position(|o| o == c);
+        position(a).cmp(&position(b))
+    }
+}
+
+#[test]
+fn runtime_collation() {
+    let collation = Collation { order: vec!['c', 'a', 'b'] };
+    let set = "abc".chars().fold(OrdSet::with_comparator(collation), |s, c| s.insert(c));
+    assert_eq!(set.iter().cloned().collect::<String>(), "cab");
+}
+
+#[test]
+#[should_panic]
+fn refuse_mixed_comparators() {
+    let a = OrdSet::with_comparator(Collation { order: vec!['a', 'b'] }).insert('a');
+    let b = OrdSet::with_comparator(Collation { order: vec!['b', 'a'] }).insert('b');
+    a.union(&b);
+}
+
+// Counts its comparisons; used only by `union_reuses_shared_subtrees`.
+#[derive(Clone, PartialEq)]
+struct Counting;
+
+static UNION_COMPARISONS: AtomicUsize = AtomicUsize::new(0);
+
+impl Comparator<u32> for Counting {
+    fn compare(&self, a: &u32, b: &u32) -> Ordering {
+        UNION_COMPARISONS.fetch_add(1, AtomicOrdering::SeqCst);
+        a.cmp(b)
+    }
+}
+
+#[test]
+fn union_reuses_shared_subtrees() {
+    let map = OrdMap::from_sorted_iter_with((0..100000).map(|k| (k * 2, k)), Counting);
+    let other = map.insert(31337, 0).remove(&4000);
+    let before = UNION_COMPARISONS.load(AtomicOrdering::SeqCst);
+    let union = map.union(&other);
+    assert!(UNION_COMPARISONS.load(AtomicOrdering::SeqCst) - before < 2000);
+    assert_eq!(union.len(), 100001);
+    assert_eq!(union.get(&31337), Some(&0));
+    assert_eq!(union.get(&4000), Some(&2000));
+}
+
+// Counts the nodes built; used only by `union_of_small_map_into_large_one`.
+struct Builds;
+
+static UNION_BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+impl Measure<u64, u64> for Builds {
+    type Summary = ();
+    fn empty() {}
+    fn measure(_: &u64, _: &u64) { UNION_BUILDS.fetch_add(1, AtomicOrdering::SeqCst); }
+    fn combine(_: &(), _: &()) {}
+}
+
+#[test]
+fn union_of_small_map_into_large_one() {
+    let big: OrdMap<u64, u64, Natural, Builds> =
+        OrdMap::from_sorted_iter_measured((0..200000).map(|k| (k * 2, k)), Natural);
+    let small: OrdMap<u64, u64, Natural, Builds> =
+        OrdMap::from_sorted_iter_measured((0..1000).map(|k| (k * 401, 7)), Natural);
+
+    let before = UNION_BUILDS.load(AtomicOrdering::SeqCst);
+    let folded = small.iter().fold(big.clone(), |m, (k, v)| m.insert(*k, *v));
+    let inserts = UNION_BUILDS.load(AtomicOrdering::SeqCst) - before;
+
+    let before = UNION_BUILDS.load(AtomicOrdering::SeqCst);
+    let union = big.union(&small);
+    let builds = UNION_BUILDS.load(AtomicOrdering::SeqCst) - before;
+
+    assert!(builds <= inserts, "union built {} nodes, inserting built {}", builds, inserts);
+    assert_eq!(union.len(), 200501);
+    assert!(union.keys().eq(folded.keys()));
+    assert_eq!(union.get(&802), Some(&401));
+    assert_eq!(union.get(&401), Some(&7));
+}
+
+struct Sum;
+
+impl Measure<u64, u64> for Sum {
+    type Summary = u64;
+    fn empty() -> u64 { 0 }
+    fn measure(_: &u64, value: &u64) -> u64 { *value }
+    fn combine(left: &u64, right: &u64) -> u64 { left + right }
+}
+
+#[test]
+fn range_sums() {
+    let map: OrdMap<u64, u64, Natural, Sum> = (0..100).map(|k| (k, k * 10)).collect();
+    assert_eq!(map.summary(), 49500);
+    assert_eq!(map.range_summary(10..20), (10..20).map(|k| k * 10).sum::<u64>());
+    assert_eq!(map.range_summary(..=5), 150);
+    assert_eq!(map.range_summary(95..), 4850);
+    assert_eq!(map.range_summary(200..), 0);
+    assert_eq!(map.range_summary((Bound::Excluded(3), Bound::Excluded(5))), 40);
+
+    let changed = map.insert(50, 0).remove(&10).update_with(7, |v| v.map(|v| v + 1));
+    assert_eq!(changed.summary(), 49500 - 500 - 100 + 1);
+    assert_eq!(map.summary(), 49500);
+}
+
+#[test]
+fn summaries_follow_rebalancing() {
+    let mut random = random_below(0x9E3779B97F4A7C15);
+    let mut map: OrdMap<u64, u64, Natural, Sum> = OrdMap::default();
+    for _ in 0..1000 {
+        let k = random(200);
+        map = if random(3) == 0 { map.remove(&k) } else { map.insert(k, random(1000)) };
+        let (a, b) = (random(200), random(200));
+        let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+        let expected: u64 = map.range(lo..hi).map(|(_, v)| *v).sum();
+        assert_eq!(map.range_summary(lo..hi), expected);
+    }
+    assert_eq!(map.summary(), map.values().sum::<u64>());
+}
+
+#[test]
+fn interval_queries() {
+    let bookings: IntervalMap<u32, &str> = vec![(9..11, "standup"), (10..12, "review"), (13..14, "lunch"), (12..12, "empty")]
+        .into_iter()
+        .collect();
+    fn names<'a>(iter: Overlapping<'a, u32, &'a str>) -> Vec<&'a str> {
+        iter.map(|(_, v)| *v).collect()
+    }
+    assert_eq!(names(bookings.overlapping(11..13)), vec!["review"]);
+    assert_eq!(names(bookings.overlapping(0..24)), vec!["standup", "review", "lunch"]);
+    assert_eq!(names(bookings.stabbing(10)), vec!["standup", "review"]);
+    assert_eq!(names(bookings.stabbing(12)), Vec::<&str>::new());
+    assert_eq!(names(bookings.overlapping(14..20)), Vec::<&str>::new());
+    assert_eq!(names(bookings.overlapping(10..10)), Vec::<&str>::new());
+
+    let cancelled = bookings.remove(&(10..12));
+    assert_eq!(names(cancelled.stabbing(10)), vec!["standup"]);
+    assert_eq!(names(bookings.stabbing(10)), vec!["standup", "review"]);
+    assert_eq!(cancelled.len(), 3);
+}
+
+#[test]
+fn overlapping_matches_a_linear_scan() {
+    let mut random = random_below(0xD1B54A32D192ED03);
+    let mut map = IntervalMap::new();
+    for i in 0..500 {
+        let start = random(1000);
+        let interval = start..start + random(50);
+        map = if random(4) == 0 { map.remove(&interval) } else { map.insert(interval, i) };
+    }
+    for _ in 0..200 {
+        let start = random(1000);
+        let query = start..start + random(30);
+        let expected: Vec<_> = map.iter()
+            .filter(|&(r, _)| r.start < query.end && query.start < r.end && r.start < r.end && query.start < query.end)
+            .collect();
+        assert_eq!(map.overlapping(query.clone()).collect::<Vec<_>>(), expected);
+        let stabbed: Vec<_> = map.iter().filter(|&(r, _)| r.start <= start && start < r.end).collect();
+        assert_eq!(map.stabbing(start).collect::<Vec<_>>(), stabbed);
+    }
+}
+
+#[test]
+fn diff_reports_changes_in_key_order() {
+    let old: OrdMap<u32, &str> = vec![(1, "a"), (2, "b"), (3, "c"), (5, "e")].into_iter().collect();
+    let new = old.remove(&2).insert(3, "C").insert(4, "d").insert(5, "e");
+    assert_eq!(OrdMap::diff(&old, &new).collect::<Vec<_>>(),
+               vec![Change::Removed(&2, &"b"), Change::Changed(&3, &"c", &"C"), Change::Added(&4, &"d")]);
+    assert_eq!(OrdMap::diff(&new, &old).count(), 3);
+    assert_eq!(OrdMap::diff(&old, &old).count(), 0);
+    assert_eq!(OrdMap::diff(&OrdMap::new(), &old).count(), 4);
+}
+
+#[test]
+fn diff_matches_a_full_comparison() {
+    let mut random = random_below(0xBF58476D1CE4E5B9);
+    let mut old = OrdMap::new();
+    for _ in 0..300 {
+        old = old.insert(random(400), random(3));
+    }
+    for _ in 0..50 {
+        let mut new = old.clone();
+        for _ in 0..random(20) {
+            let k = random(400);
+            new = if random(2) == 0 { new.remove(&k) } else { new.insert(k, random(3)) };
+        }
+        let full: Vec<_> = (0..400u64).filter_map(|k| match (old.get(&k), new.get(&k)) {
+            (Some(a), None) => Some((k, Some(*a), None)),
+            (None, Some(b)) => Some((k, None, Some(*b))),
+            (Some(a), Some(b)) if a != b => Some((k, Some(*a), Some(*b))),
+            _ => None,
+        }).collect();
+        let structural: Vec<_> = OrdMap::diff(&old, &new).map(|change| match change {
+            Change::Removed(k, a) => (*k, Some(*a), None),
+            Change::Added(k, b) => (*k, None, Some(*b)),
+            Change::Changed(k, a, b) => (*k, Some(*a), Some(*b)),
+        }).collect();
+        assert_eq!(structural, full);
+        old = new;
+    }
+}
+
+#[derive(Clone)]
+struct Counted(u32);
+
+static COMPARISONS: AtomicUsize = AtomicUsize::new(0);
+
+impl PartialEq for Counted {
+    fn eq(&self, other: &Counted) -> bool {
+        COMPARISONS.fetch_add(1, AtomicOrdering::SeqCst);
+        self.0 == other.0
+    }
+}
+
+#[test]
+fn diff_skips_shared_subtrees() {
+    let old: OrdMap<u32, Counted> = OrdMap::from_sorted_iter((0..100000).map(|k| (k, Counted(k))));
+    let new = old.insert(31337, Counted(0)).remove(&777);
+    assert_eq!(OrdMap::diff(&old, &new).count(), 2);
+    assert!(COMPARISONS.load(AtomicOrdering::SeqCst) < 100);
+}
+
+#[test]
+fn intersection_with_combines_values() {
+    let a: OrdMap<u32, u32> = vec![(1, 10), (2, 20), (3, 30)].into_iter().collect();
+    let b: OrdMap<u32, u32> = vec![(2, 2), (3, 3), (4, 4)].into_iter().collect();
+    let product = a.intersection_with(&b, |_, x, y| x * y);
+    assert_eq!(product.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), vec![(2, 40), (3, 90)]);
+    assert_eq!(a.intersection_with(&a, |_, x, y| x + y).get(&1), Some(&20));
+}
+
+#[test]
+fn cursor_seeks_and_steps() {
+    let map: OrdMap<u32, char> = vec![(10, 'a'), (20, 'b'), (30, 'c'), (40, 'd')].into_iter().collect();
+    let mut cursor = map.cursor();
+    assert_eq!(cursor.entry(), Some((&10, &'a')));
+    assert_eq!(cursor.move_next(), Some((&20, &'b')));
+    assert_eq!(cursor.move_prev(), Some((&10, &'a')));
+    assert_eq!(cursor.move_prev(), None);
+    assert_eq!(cursor.move_prev(), Some((&40, &'d')));
+    assert_eq!(cursor.move_next(), None);
+    assert_eq!(cursor.move_next(), Some((&10, &'a')));
+
+    assert!(cursor.seek(&30));
+    assert_eq!(cursor.value(), Some(&'c'));
+    assert!(!cursor.seek(&25));
+    assert_eq!(cursor.key(), None);
+    cursor.seek_at_or_after(&25);
+    assert_eq!(cursor.key(), Some(&30));
+    cursor.seek_at_or_before(&25);
+    assert_eq!(cursor.key(), Some(&20));
+    cursor.seek_at_or_after(&41);
+    assert_eq!(cursor.key(), None);
+    cursor.seek_at_or_before(&9);
+    assert_eq!(cursor.key(), None);
+    cursor.seek_at_or_before(&10);
+    assert_eq!(cursor.key(), Some(&10));
+}
+
+#[test]
+fn cursor_walks_every_entry() {
+    let map: OrdMap<u32, u32> = (0..1000).map(|k| (k * 2, k)).collect();
+    let mut cursor = map.cursor();
+    let mut forward = Vec::new();
+    while let Some((k, _)) = cursor.entry() {
+        forward.push(*k);
+        cursor.move_next();
+    }
+    assert_eq!(forward, map.keys().cloned().collect::<Vec<_>>());
+    let mut backward = Vec::new();
+    while let Some((k, _)) = cursor.move_prev() {
+        backward.push(*k);
+    }
+    forward.reverse();
+    assert_eq!(backward, forward);
+    for k in 0..2001 {
+        cursor.seek_at_or_after(&k);
+        assert_eq!(cursor.key().cloned(), map.range(k..).next().map(|(k, _)| *k));
+        cursor.seek_at_or_before(&k);
+        assert_eq!(cursor.key().cloned(), map.range(..=k).next_back().map(|(k, _)| *k));
+    }
+}
+
+#[test]
+fn cursor_edits_produce_new_maps() {
+    let map: OrdMap<u32, char> = vec![(1, 'a'), (2, 'b'), (3, 'c')].into_iter().collect();
+    let mut cursor = map.cursor();
+    cursor.seek(&2);
+    assert_eq!(cursor.replace('B').get(&2), Some(&'B'));
+    assert_eq!(cursor.remove().keys().cloned().collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(cursor.move_next(), Some((&3, &'c')));
+    assert_eq!(map.len(), 3);
+    cursor.move_next();
+    assert_eq!(cursor.remove().len(), 3);
+}
+
+#[test]
+fn floor_ceiling_lower_higher() {
+    let versions: OrdMap<u32, &str> = vec![(100, "v1"), (200, "v2"), (300, "v3")].into_iter().collect();
+    assert_eq!(versions.floor(&250), Some((&200, &"v2")));
+    assert_eq!(versions.floor(&200), Some((&200, &"v2")));
+    assert_eq!(versions.floor(&99), None);
+    assert_eq!(versions.ceiling(&201), Some((&300, &"v3")));
+    assert_eq!(versions.ceiling(&301), None);
+    assert_eq!(versions.lower(&200), Some((&100, &"v1")));
+    assert_eq!(versions.higher(&200), Some((&300, &"v3")));
+    assert_eq!(versions.higher(&300), None);
+
+    let set: OrdSet<u32> = (0..500).map(|i| i * 3).collect();
+    for k in 0..1510 {
+        assert_eq!(set.floor(&k), set.range(..=k).next_back());
+        assert_eq!(set.ceiling(&k), set.range(k..).next());
+        assert_eq!(set.lower(&k), set.range(..k).next_back());
+        assert_eq!(set.higher(&k), set.range((Bound::Excluded(k), Bound::Unbounded)).next());
+    }
+}
@@ -8,3 +8,181 @@ fn from_vec65536() {
         assert_eq!(v[i], i)
     }
 }
+
+#[test]
+fn set_and_update() {
+    let v: Vector<u32> = (0..1000).collect();
+    let w = v.set(500, &0).unwrap().update(999, |x| x * 2).unwrap();
+    assert_eq!(w[500], 0);
+    assert_eq!(w[999], 1998);
+    assert_eq!(v[500], 500);
+    assert_eq!(v[999], 999);
+    assert_eq!(w.len(), 1000);
+    assert!(v.set(1000, &0).is_none());
+    assert!(v.update(5000, |x| *x).is_none());
+    assert_eq!(v.get(1000), None);
+}
+
+#[test]
+fn pop_and_truncate() {
+    let v: Vector<u32> = (0..2000).collect();
+    let (last, rest) = v.pop().unwrap();
+    assert_eq!(last, 1999);
+    assert_eq!(rest.len(), 1999);
+    assert_eq!(v.len(), 2000);
+    assert!(Vector::<u32>::new().pop().is_none());
+
+    for &len in &[1025, 1024, 1023, 33, 32, 31, 1, 0] {
+        let short = v.truncate(len);
+        assert_eq!(short.len(), len);
+        assert_eq!(short.get(len), None);
+        let regrown = (len..1100).fold(short, |w, i| w.append(&(i + 5000)));
+        for i in 0..1100 {
+            assert_eq!(regrown[i], if i < len { i } else { i + 5000 });
+        }
+    }
+    assert_eq!(v.drop_last(3).len(), 1997);
+    assert!(v.drop_last(3000).is_empty());
+    assert_eq!(v.truncate(5000).len(), 2000);
+}
+
+#[test]
+fn pop_everything() {
+    let mut v: Vector<u32> = (0..100).collect();
+    let mut popped = Vec::new();
+    while let Some((x, rest)) = v.pop() {
+        popped.push(x);
+        v = rest;
+    }
+    assert_eq!(popped, (0..100).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn tail_and_trie_boundaries() {
+    for &n in &[31, 32, 33, 64, 65, 1056, 1057, 33824] {
+        let v: Vector<u32> = (0..n).collect();
+        assert_eq!(v.len(), n);
+        for i in 0..n {
+            assert_eq!(v[i], i);
+        }
+        let first = v.set(0, &1000000).unwrap();
+        let last = v.set(n - 1, &2000000).unwrap();
+        assert_eq!((first[0], first[n - 1]), (1000000, n - 1));
+        assert_eq!((last[0], last[n - 1]), (0, 2000000));
+        assert_eq!(v[0], 0);
+    }
+}
+
+fn contents(v: &Vector<u32>) -> Vec<u32> {
+    (0..v.len()).map(|i| v[i]).collect()
+}
+
+#[test]
+fn concat_and_split() {
+    let a: Vector<u32> = (0..1000).collect();
+    let b: Vector<u32> = (1000..1037).collect();
+    let joined = a.concat(&b);
+    assert_eq!(contents(&joined), (0..1037).collect::<Vec<_>>());
+    assert_eq!(contents(&b.concat(&a)), (1000..1037).chain(0..1000).collect::<Vec<_>>());
+    assert_eq!(contents(&Vector::new().concat(&b)), contents(&b));
+
+    let (front, back) = joined.split_at(500).unwrap();
+    assert_eq!(contents(&front), (0..500).collect::<Vec<_>>());
+    assert_eq!(contents(&back), (500..1037).collect::<Vec<_>>());
+    assert_eq!(contents(&back.concat(&front).append(&7)), (500..1037).chain(0..500).chain(7..8).collect::<Vec<_>>());
+    assert!(joined.split_at(1038).is_none());
+    assert_eq!(joined.split_at(1037).unwrap().1.len(), 0);
+}
+
+#[test]
+fn insert_and_remove() {
+    let v: Vector<u32> = (0..100).collect();
+    let w = v.insert(50, &1000).unwrap();
+    assert_eq!(w.len(), 101);
+    assert_eq!((w[49], w[50], w[51]), (49, 1000, 50));
+    let (removed, x) = w.remove(50).unwrap();
+    assert_eq!(removed, 1000);
+    assert_eq!(contents(&x), contents(&v));
+    assert!(v.insert(101, &0).is_none());
+    assert!(v.remove(100).is_none());
+    assert_eq!(contents(&v.insert(100, &5).unwrap())[100], 5);
+}
+
+// A deterministic generator of numbers below its argument, for the randomized
+// tests.
+fn random_below(mut seed: u64) -> impl FnMut(u32) -> u32 {
+    move |n| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % u64::from(n)) as u32
+    }
+}
+
+#[test]
+fn matches_a_vec_under_random_edits() {
+    let mut random = random_below(0x94D049BB133111EB);
+    let mut vector: Vector<u32> = Vector::new();
+    let mut model: Vec<u32> = Vec::new();
+    let mut pieces: Vec<(Vector<u32>, Vec<u32>)> = Vec::new();
+    for step in 0..3000 {
+        let len = model.len() as u32;
+        match random(8) {
+            0 => {
+                let i = random(len + 1);
+                vector = vector.insert(i, &step).unwrap();
+                model.insert(i as usize, step);
+            },
+            1 if len > 0 => {
+                let i = random(len);
+                let (value, rest) = vector.remove(i).unwrap();
+                assert_eq!(value, model.remove(i as usize));
+                vector = rest;
+            },
+            2 => {
+                let i = random(len + 1);
+                let (front, back) = vector.split_at(i).unwrap();
+                pieces.push((back, model.split_off(i as usize)));
+                vector = front;
+            },
+            3 if !pieces.is_empty() => {
+                let (piece, items) = pieces.swap_remove(random(pieces.len() as u32) as usize);
+                vector = vector.concat(&piece);
+                model.extend(items);
+            },
+            4 if len > 0 => {
+                let i = random(len);
+                vector = vector.set(i, &step).unwrap();
+                model[i as usize] = step;
+            },
+            5 => {
+                let n = random(100);
+                vector = vector.truncate(n);
+                model.truncate(n as usize);
+            },
+            _ => {
+                for k in 0..random(80) {
+                    vector = vector.append(&k);
+                    model.push(k);
+                }
+            },
+        }
+        assert_eq!(vector.len() as usize, model.len());
+        assert_eq!(contents(&vector), model);
+    }
+}
+
+#[test]
+fn repeated_concatenation() {
+    let mut v = Vector::new();
+    let mut model = Vec::new();
+    for i in 0..300 {
+        let piece: Vector<u32> = (0..i % 50).map(|k| k + i).collect();
+        model.extend((0..i % 50).map(|k| k + i));
+        v = v.concat(&piece);
+    }
+    assert_eq!(contents(&v), model);
+    let mut w = Vector::new();
+    for i in 0..200 {
+        w = Vector::new().append(&i).concat(&w);
+    }
+    assert_eq!(contents(&w), (0..200).rev().collect::<Vec<_>>());
+}